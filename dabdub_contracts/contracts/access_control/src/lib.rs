@@ -0,0 +1,136 @@
+#![no_std]
+
+//! Role-based access control shared by the dabdub contracts. Roles are kept
+//! in the calling contract's instance storage; each contract publishes its
+//! own role events.
+
+mod test;
+
+use soroban_sdk::{contracttype, symbol_short, Address, Env, Symbol, Vec};
+
+// Role constants
+pub const ADMIN_ROLE: Symbol = symbol_short!("ADMIN");
+pub const OPERATOR_ROLE: Symbol = symbol_short!("OPERATOR");
+pub const TREASURER_ROLE: Symbol = symbol_short!("TREASR");
+pub const BACKEND_ROLE: Symbol = symbol_short!("BACKEND");
+pub const COMPLIANCE_ROLE: Symbol = symbol_short!("COMPLY");
+
+#[contracttype]
+#[derive(Clone)]
+pub enum RoleKey {
+    Primary(Symbol), // single address shown as the holder of a role
+    Roles(Address),
+    Count(Symbol), // number of addresses holding a role
+}
+
+/// Grant a role to an address. Returns false if it already had the role.
+pub fn grant_role(env: &Env, account: Address, role: Symbol) -> bool {
+    let mut roles: Vec<Symbol> = env
+        .storage()
+        .instance()
+        .get(&RoleKey::Roles(account.clone()))
+        .unwrap_or(Vec::new(env));
+
+    // Check if already has role
+    for r in roles.iter() {
+        if r == role {
+            return false;
+        }
+    }
+
+    roles.push_back(role.clone());
+    env.storage()
+        .instance()
+        .set(&RoleKey::Roles(account), &roles);
+    let count = role_count(env, role.clone());
+    env.storage()
+        .instance()
+        .set(&RoleKey::Count(role), &(count + 1));
+    true
+}
+
+/// Revoke a role from an address. Returns false if it did not have the role;
+/// the last admin cannot be revoked.
+pub fn revoke_role(env: &Env, account: Address, role: Symbol) -> bool {
+    if !has_role(env, &account, role.clone()) {
+        return false;
+    }
+    let count = role_count(env, role.clone());
+    if role == ADMIN_ROLE && count <= 1 {
+        panic!("Cannot revoke last admin");
+    }
+
+    let roles: Vec<Symbol> = env
+        .storage()
+        .instance()
+        .get(&RoleKey::Roles(account.clone()))
+        .unwrap_or(Vec::new(env));
+
+    let mut new_roles = Vec::new(env);
+    for r in roles.iter() {
+        if r != role {
+            new_roles.push_back(r);
+        }
+    }
+
+    env.storage()
+        .instance()
+        .set(&RoleKey::Roles(account), &new_roles);
+    env.storage()
+        .instance()
+        .set(&RoleKey::Count(role), &count.saturating_sub(1));
+    true
+}
+
+/// Number of addresses holding `role`
+pub fn role_count(env: &Env, role: Symbol) -> u32 {
+    env.storage()
+        .instance()
+        .get(&RoleKey::Count(role))
+        .unwrap_or(0)
+}
+
+/// Set the primary holder of `role`, e.g. the admin shown by `get_admin`
+pub fn set_primary(env: &Env, role: Symbol, account: &Address) {
+    env.storage()
+        .instance()
+        .set(&RoleKey::Primary(role), account);
+}
+
+/// Primary holder of `role`, if one was set
+pub fn get_primary(env: &Env, role: Symbol) -> Option<Address> {
+    env.storage().instance().get(&RoleKey::Primary(role))
+}
+
+/// Check if address has role
+pub fn has_role(env: &Env, account: &Address, role: Symbol) -> bool {
+    let roles: Vec<Symbol> = env
+        .storage()
+        .instance()
+        .get(&RoleKey::Roles(account.clone()))
+        .unwrap_or(Vec::new(env));
+
+    for r in roles.iter() {
+        if r == role {
+            return true;
+        }
+    }
+    false
+}
+
+/// Require that caller has specific role (panics if not)
+pub fn require_role(env: &Env, account: &Address, role: Symbol) {
+    if !has_role(env, account, role) {
+        panic!("Missing required role");
+    }
+}
+
+/// Require that caller has at least one of `roles` (panics if not)
+pub fn require_any_role(env: &Env, account: &Address, roles: &[Symbol]) {
+    if !roles
+        .iter()
+        .any(|role| has_role(env, account, role.clone()))
+    {
+        panic!("Missing required role");
+    }
+}
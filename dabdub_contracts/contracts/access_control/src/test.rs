@@ -0,0 +1,74 @@
+#![cfg(test)]
+use crate::{
+    grant_role, has_role, require_any_role, require_role, revoke_role, role_count, ADMIN_ROLE,
+    OPERATOR_ROLE, TREASURER_ROLE,
+};
+use soroban_sdk::{contract, testutils::Address as _, Address, Env};
+
+#[contract]
+struct RolesContract;
+
+#[test]
+fn test_grant_and_revoke_role() {
+    let env = Env::default();
+    let contract_id = env.register(RolesContract, ());
+    let account = Address::generate(&env);
+
+    env.as_contract(&contract_id, || {
+        assert!(!has_role(&env, &account, OPERATOR_ROLE));
+
+        assert!(grant_role(&env, account.clone(), OPERATOR_ROLE));
+        assert!(!grant_role(&env, account.clone(), OPERATOR_ROLE));
+        assert!(has_role(&env, &account, OPERATOR_ROLE));
+        assert!(!has_role(&env, &account, ADMIN_ROLE));
+
+        assert_eq!(role_count(&env, OPERATOR_ROLE), 1);
+
+        assert!(revoke_role(&env, account.clone(), OPERATOR_ROLE));
+        assert!(!revoke_role(&env, account.clone(), OPERATOR_ROLE));
+        assert!(!has_role(&env, &account, OPERATOR_ROLE));
+        assert_eq!(role_count(&env, OPERATOR_ROLE), 0);
+    });
+}
+
+#[test]
+#[should_panic(expected = "Cannot revoke last admin")]
+fn test_revoke_last_admin() {
+    let env = Env::default();
+    let contract_id = env.register(RolesContract, ());
+    let first = Address::generate(&env);
+    let second = Address::generate(&env);
+
+    env.as_contract(&contract_id, || {
+        grant_role(&env, first.clone(), ADMIN_ROLE);
+        grant_role(&env, second.clone(), ADMIN_ROLE);
+        assert!(revoke_role(&env, first.clone(), ADMIN_ROLE));
+        revoke_role(&env, second.clone(), ADMIN_ROLE);
+    });
+}
+
+#[test]
+fn test_require_any_role() {
+    let env = Env::default();
+    let contract_id = env.register(RolesContract, ());
+    let account = Address::generate(&env);
+
+    env.as_contract(&contract_id, || {
+        grant_role(&env, account.clone(), TREASURER_ROLE);
+        require_role(&env, &account, TREASURER_ROLE);
+        require_any_role(&env, &account, &[ADMIN_ROLE, TREASURER_ROLE]);
+    });
+}
+
+#[test]
+#[should_panic(expected = "Missing required role")]
+fn test_require_any_role_missing() {
+    let env = Env::default();
+    let contract_id = env.register(RolesContract, ());
+    let account = Address::generate(&env);
+
+    env.as_contract(&contract_id, || {
+        grant_role(&env, account.clone(), TREASURER_ROLE);
+        require_any_role(&env, &account, &[ADMIN_ROLE, OPERATOR_ROLE]);
+    });
+}
@@ -3,7 +3,7 @@
 mod test;
 
 use soroban_sdk::{
-    contract, contractevent, contractimpl, contracttype, Address, BytesN, Env,
+    contract, contractevent, contractimpl, contracttype, Address, BytesN, Env, Symbol,
 };
 
 // --- Types ---
@@ -34,8 +34,6 @@ pub struct PaymentRequest {
 #[contracttype]
 #[derive(Clone)]
 pub enum DataKey {
-    Admin,
-    Operator,
     Vault,
     Paused,
     MinAmount,
@@ -79,6 +77,18 @@ struct OperatorUpdatedEvent {
     new_operator: Address,
 }
 
+#[contractevent(topics = ["PAY_REQ", "role"])]
+struct RoleGrantedEvent {
+    account: Address,
+    role: Symbol,
+}
+
+#[contractevent(topics = ["PAY_REQ", "role"])]
+struct RoleRevokedEvent {
+    account: Address,
+    role: Symbol,
+}
+
 #[contractevent(topics = ["PAY_REQ", "config"])]
 struct MinAmountUpdatedEvent {
     old_min: i128,
@@ -117,9 +127,11 @@ impl PaymentRequestContract {
             panic!("Min amount must be less than max amount");
         }
 
-        env.storage().instance().set(&DataKey::Admin, &admin);
-        env.storage().instance().set(&DataKey::Operator, &operator);
         env.storage().instance().set(&DataKey::Vault, &vault);
+        access_control::set_primary(&env, access_control::ADMIN_ROLE, &admin);
+        access_control::set_primary(&env, access_control::OPERATOR_ROLE, &operator);
+        Self::grant(&env, admin, access_control::ADMIN_ROLE);
+        Self::grant(&env, operator, access_control::OPERATOR_ROLE);
         env.storage().instance().set(&DataKey::Paused, &false);
         env.storage()
             .instance()
@@ -305,8 +317,9 @@ impl PaymentRequestContract {
             panic!("Request is not pending");
         }
 
-        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
-        if caller != request.merchant && caller != admin {
+        if caller != request.merchant
+            && !access_control::has_role(&env, &caller, access_control::ADMIN_ROLE)
+        {
             panic!("Not authorized to cancel");
         }
 
@@ -399,11 +412,11 @@ impl PaymentRequestContract {
     }
 
     pub fn get_admin(env: Env) -> Address {
-        env.storage().instance().get(&DataKey::Admin).unwrap()
+        access_control::get_primary(&env, access_control::ADMIN_ROLE).unwrap()
     }
 
     pub fn get_operator(env: Env) -> Address {
-        env.storage().instance().get(&DataKey::Operator).unwrap()
+        access_control::get_primary(&env, access_control::OPERATOR_ROLE).unwrap()
     }
 
     pub fn get_vault(env: Env) -> Address {
@@ -441,15 +454,15 @@ impl PaymentRequestContract {
 
     // --- Admin Functions ---
 
-    /// Update operator address (admin only)
+    /// Replace the primary operator address, moving its operator role (admin only)
     pub fn set_operator(env: Env, caller: Address, new_operator: Address) {
         caller.require_auth();
         Self::require_admin(&env, &caller);
 
-        let old_operator: Address = env.storage().instance().get(&DataKey::Operator).unwrap();
-        env.storage()
-            .instance()
-            .set(&DataKey::Operator, &new_operator);
+        let old_operator = Self::get_operator(env.clone());
+        access_control::set_primary(&env, access_control::OPERATOR_ROLE, &new_operator);
+        Self::revoke(&env, old_operator.clone(), access_control::OPERATOR_ROLE);
+        Self::grant(&env, new_operator.clone(), access_control::OPERATOR_ROLE);
 
         OperatorUpdatedEvent {
             old_operator,
@@ -510,6 +523,26 @@ impl PaymentRequestContract {
         .publish(&env);
     }
 
+    /// Grant a role, e.g. an additional operator (admin only)
+    pub fn grant_role(env: Env, caller: Address, account: Address, role: Symbol) {
+        caller.require_auth();
+        Self::require_admin(&env, &caller);
+
+        Self::grant(&env, account, role);
+    }
+
+    /// Revoke a role (admin only)
+    pub fn revoke_role(env: Env, caller: Address, account: Address, role: Symbol) {
+        caller.require_auth();
+        Self::require_admin(&env, &caller);
+
+        Self::revoke(&env, account, role);
+    }
+
+    pub fn has_role(env: Env, account: Address, role: Symbol) -> bool {
+        access_control::has_role(&env, &account, role)
+    }
+
     /// Pause contract (admin only)
     pub fn pause(env: Env, caller: Address) {
         caller.require_auth();
@@ -529,17 +562,30 @@ impl PaymentRequestContract {
     // --- Internal Helpers ---
 
     fn require_admin(env: &Env, caller: &Address) {
-        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
-        if *caller != admin {
+        if !access_control::has_role(env, caller, access_control::ADMIN_ROLE) {
             panic!("Not admin");
         }
     }
 
     fn require_operator_or_admin(env: &Env, caller: &Address) {
-        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
-        let operator: Address = env.storage().instance().get(&DataKey::Operator).unwrap();
-        if *caller != admin && *caller != operator {
+        if !access_control::has_role(env, caller, access_control::ADMIN_ROLE)
+            && !access_control::has_role(env, caller, access_control::OPERATOR_ROLE)
+        {
             panic!("Not operator or admin");
         }
     }
+
+    /// Grant `role` to `account`, emitting an event if it was newly granted
+    fn grant(env: &Env, account: Address, role: Symbol) {
+        if access_control::grant_role(env, account.clone(), role.clone()) {
+            RoleGrantedEvent { account, role }.publish(env);
+        }
+    }
+
+    /// Revoke `role` from `account`, emitting an event if it held the role
+    fn revoke(env: &Env, account: Address, role: Symbol) {
+        if access_control::revoke_role(env, account.clone(), role.clone()) {
+            RoleRevokedEvent { account, role }.publish(env);
+        }
+    }
 }
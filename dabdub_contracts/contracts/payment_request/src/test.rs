@@ -1,6 +1,7 @@
 #![cfg(test)]
 
 use crate::{PaymentRequestContract, PaymentRequestContractClient, PaymentRequestStatus};
+use access_control::OPERATOR_ROLE;
 use soroban_sdk::{testutils::Address as _, testutils::Ledger, Address, BytesN, Env};
 
 fn setup_env() -> (
@@ -475,6 +476,36 @@ fn test_set_operator_not_admin() {
     client.set_operator(&random, &new_operator);
 }
 
+#[test]
+fn test_set_operator_moves_role() {
+    let (env, client, admin, operator, _vault) = setup_env();
+    let new_operator = Address::generate(&env);
+
+    client.set_operator(&admin, &new_operator);
+    assert!(!client.has_role(&operator, &OPERATOR_ROLE));
+    assert!(client.has_role(&new_operator, &OPERATOR_ROLE));
+}
+
+#[test]
+fn test_multiple_operators() {
+    let (env, client, admin, operator, _vault) = setup_env();
+    let second_operator = Address::generate(&env);
+    let merchant = Address::generate(&env);
+
+    client.grant_role(&admin, &second_operator, &OPERATOR_ROLE);
+
+    env.ledger().set_timestamp(1000);
+    client.create_request(&merchant, &make_id(&env, 1), &10_000_000i128, &make_id(&env, 2), &2000);
+    client.create_request(&merchant, &make_id(&env, 3), &10_000_000i128, &make_id(&env, 4), &2000);
+
+    client.mark_paid(&operator, &make_id(&env, 1), &make_id(&env, 10));
+    client.mark_paid(&second_operator, &make_id(&env, 3), &make_id(&env, 11));
+    assert_eq!(client.get_pending_requests(), 0);
+
+    client.revoke_role(&admin, &second_operator, &OPERATOR_ROLE);
+    assert!(!client.has_role(&second_operator, &OPERATOR_ROLE));
+}
+
 #[test]
 fn test_set_min_amount() {
     let (_env, client, admin, _operator, _vault) = setup_env();
@@ -0,0 +1,355 @@
+use soroban_sdk::{
+    auth::{Context, CustomAccountInterface},
+    contracterror, contractimpl, contracttype,
+    crypto::Hash,
+    Address, Bytes, BytesN, Env, Symbol, TryFromVal, Val, Vec,
+};
+
+use crate::{BackendMode, DataKey, UserWallet, UserWalletArgs, UserWalletClient, WalletStatus};
+
+/// Public key of a signer that can authorize calls on behalf of the wallet.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub enum SignerKey {
+    Ed25519(BytesN<32>),
+    Secp256r1(BytesN<65>),
+}
+
+/// What a signer may authorize. `Owner` signers can authorize anything while
+/// the wallet is active, and only calls into the wallet itself once it is
+/// frozen or closed; `Backend` signers only `transfer_to_vault` on this
+/// wallet, within the allowlist and spending limits, and nothing once the
+/// backend is revoked.
+#[contracttype]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SignerRole {
+    Owner,
+    Backend,
+}
+
+/// A WebAuthn `get` assertion produced by a passkey over the signature payload.
+#[contracttype]
+#[derive(Clone)]
+pub struct WebAuthnAssertion {
+    pub authenticator_data: Bytes,
+    pub client_data_json: Bytes,
+    pub signature: BytesN<64>,
+}
+
+#[contracttype]
+#[derive(Clone)]
+pub enum SignatureProof {
+    /// Signature directly over the signature payload.
+    Raw(BytesN<64>),
+    /// WebAuthn assertion whose challenge is the signature payload
+    /// (secp256r1 signers only).
+    WebAuthn(WebAuthnAssertion),
+}
+
+#[contracttype]
+#[derive(Clone)]
+pub struct AccountSignature {
+    pub signer: SignerKey,
+    pub proof: SignatureProof,
+}
+
+/// A short-lived key that may only call `allowed_fns` on this wallet, moving
+/// at most `spend_cap` in total, until `expiry_ledger`.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionKey {
+    pub key: SignerKey,
+    pub allowed_fns: Vec<Symbol>,
+    pub spend_cap: i128,
+    pub spent: i128,
+    pub expiry_ledger: u32,
+}
+
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum AccountError {
+    NoSignatures = 1,
+    UnknownSigner = 2,
+    DuplicateSigner = 3,
+    NotPermitted = 4,
+    UnsupportedProof = 5,
+    InvalidAuthenticatorData = 6,
+    InvalidClientData = 7,
+    SessionExpired = 8,
+    SpendCapExceeded = 9,
+    BackendRevoked = 10,
+    WalletNotActive = 11,
+}
+
+const MAX_CLIENT_DATA_LEN: u32 = 1024;
+const AUTH_DATA_MIN_LEN: u32 = 37; // rpIdHash (32) + flags (1) + signCount (4)
+const FLAG_USER_PRESENT: u8 = 0x01;
+// Wallet functions that move funds, with the position of their amount argument
+// and, for vault payments, of the most fee charged on top.
+const SPENDING_FNS: [(&str, u32, Option<u32>); 3] = [
+    ("transfer_to_vault", 1, Some(4)),
+    ("withdraw", 1, None),
+    ("withdraw_asset", 2, None),
+];
+const BASE64URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+#[contractimpl]
+impl CustomAccountInterface for UserWallet {
+    type Signature = Vec<AccountSignature>;
+    type Error = AccountError;
+
+    #[allow(non_snake_case)]
+    fn __check_auth(
+        env: Env,
+        signature_payload: Hash<32>,
+        signatures: Vec<AccountSignature>,
+        auth_contexts: Vec<Context>,
+    ) -> Result<(), AccountError> {
+        if signatures.is_empty() {
+            return Err(AccountError::NoSignatures);
+        }
+
+        // A session key signs on its own and is checked against its scope.
+        if signatures.len() == 1 {
+            let sig = signatures.get_unchecked(0);
+            let session: Option<SessionKey> = env
+                .storage()
+                .instance()
+                .get(&DataKey::Session(sig.signer.clone()));
+            if let Some(session) = session {
+                verify_signature(&env, &signature_payload, &sig)?;
+                return check_session(&env, session, &auth_contexts);
+            }
+        }
+
+        let mut has_owner_signer = false;
+        for (i, sig) in signatures.iter().enumerate() {
+            // Each signer may only appear once so it can't satisfy a policy twice.
+            for prev in signatures.iter().take(i) {
+                if prev.signer == sig.signer {
+                    return Err(AccountError::DuplicateSigner);
+                }
+            }
+
+            let role: SignerRole = env
+                .storage()
+                .instance()
+                .get(&DataKey::Signer(sig.signer.clone()))
+                .ok_or(AccountError::UnknownSigner)?;
+            verify_signature(&env, &signature_payload, &sig)?;
+
+            if role == SignerRole::Owner {
+                has_owner_signer = true;
+            }
+        }
+
+        if has_owner_signer {
+            // A frozen or closed wallet's funds may only move through its own
+            // entry points, which enforce the status themselves.
+            if UserWallet::get_status(env.clone()) != WalletStatus::Active {
+                let current = env.current_contract_address();
+                for context in auth_contexts.iter() {
+                    match context {
+                        Context::Contract(c) if c.contract == current => {}
+                        _ => return Err(AccountError::WalletNotActive),
+                    }
+                }
+            }
+            return Ok(());
+        }
+        if UserWallet::get_backend_mode(env.clone()) == BackendMode::Revoked {
+            return Err(AccountError::BackendRevoked);
+        }
+
+        for context in auth_contexts.iter() {
+            let call = match context {
+                Context::Contract(c)
+                    if c.contract == env.current_contract_address()
+                        && c.fn_name == Symbol::new(&env, "transfer_to_vault") =>
+                {
+                    c
+                }
+                _ => return Err(AccountError::NotPermitted),
+            };
+
+            // Same allowlist and limits as a backend calling `transfer_to_vault`
+            // directly, counting `max_fee` since the fee is not known yet.
+            let amount = i128_arg(&env, &call.args, 1)?;
+            let recipient = call
+                .args
+                .get(3)
+                .and_then(|v| Address::try_from_val(&env, &v).ok())
+                .ok_or(AccountError::NotPermitted)?;
+            let max_fee = i128_arg(&env, &call.args, 4)?;
+            if !UserWallet::is_recipient_allowed(env.clone(), recipient) {
+                return Err(AccountError::NotPermitted);
+            }
+            let total = amount
+                .checked_add(max_fee)
+                .ok_or(AccountError::NotPermitted)?;
+            UserWallet::consume_spending_limit(&env, total);
+        }
+
+        Ok(())
+    }
+}
+
+/// The `i128` argument at `index` of an authorized call
+fn i128_arg(env: &Env, args: &Vec<Val>, index: u32) -> Result<i128, AccountError> {
+    args.get(index)
+        .and_then(|v| i128::try_from_val(env, &v).ok())
+        .ok_or(AccountError::NotPermitted)
+}
+
+/// Verify `sig` over the payload. The host verifiers panic on a bad signature.
+fn verify_signature(
+    env: &Env,
+    signature_payload: &Hash<32>,
+    sig: &AccountSignature,
+) -> Result<(), AccountError> {
+    match (&sig.signer, &sig.proof) {
+        (SignerKey::Ed25519(public_key), SignatureProof::Raw(signature)) => {
+            env.crypto()
+                .ed25519_verify(public_key, &signature_payload.clone().into(), signature);
+        }
+        (SignerKey::Secp256r1(public_key), SignatureProof::Raw(signature)) => {
+            env.crypto()
+                .secp256r1_verify(public_key, signature_payload, signature);
+        }
+        (SignerKey::Secp256r1(public_key), SignatureProof::WebAuthn(assertion)) => {
+            verify_webauthn(env, public_key, signature_payload, assertion)?;
+        }
+        (SignerKey::Ed25519(_), SignatureProof::WebAuthn(_)) => {
+            return Err(AccountError::UnsupportedProof);
+        }
+    }
+    Ok(())
+}
+
+/// Every call must be an allowed wallet function and the session's cumulative
+/// spend, fees included, must stay within its cap. The cap is in the
+/// settlement asset, so sessions cannot withdraw other assets.
+fn check_session(
+    env: &Env,
+    mut session: SessionKey,
+    auth_contexts: &Vec<Context>,
+) -> Result<(), AccountError> {
+    if env.ledger().sequence() > session.expiry_ledger {
+        return Err(AccountError::SessionExpired);
+    }
+
+    for context in auth_contexts.iter() {
+        let call = match context {
+            Context::Contract(c) if c.contract == env.current_contract_address() => c,
+            _ => return Err(AccountError::NotPermitted),
+        };
+        if !session.allowed_fns.contains(&call.fn_name) {
+            return Err(AccountError::NotPermitted);
+        }
+
+        let spend = SPENDING_FNS
+            .iter()
+            .find(|(name, _, _)| call.fn_name == Symbol::new(env, name));
+        if let Some((name, amount_index, fee_index)) = spend {
+            if *name == "withdraw_asset" {
+                let asset = call
+                    .args
+                    .get(1)
+                    .and_then(|v| Address::try_from_val(env, &v).ok())
+                    .ok_or(AccountError::NotPermitted)?;
+                let usdc_token: Address =
+                    env.storage().instance().get(&DataKey::UsdcToken).unwrap();
+                if asset != usdc_token {
+                    return Err(AccountError::NotPermitted);
+                }
+            }
+
+            let mut amount = i128_arg(env, &call.args, *amount_index)?;
+            if let Some(index) = fee_index {
+                amount = amount
+                    .checked_add(i128_arg(env, &call.args, *index)?)
+                    .ok_or(AccountError::SpendCapExceeded)?;
+            }
+            session.spent = session
+                .spent
+                .checked_add(amount)
+                .ok_or(AccountError::SpendCapExceeded)?;
+        }
+    }
+
+    if session.spent > session.spend_cap {
+        return Err(AccountError::SpendCapExceeded);
+    }
+    env.storage()
+        .instance()
+        .set(&DataKey::Session(session.key.clone()), &session);
+    Ok(())
+}
+
+/// Check a WebAuthn assertion: the authenticator saw the user, the client data
+/// is a `webauthn.get` for `payload`, and the key signed
+/// `authenticator_data || sha256(client_data_json)`.
+fn verify_webauthn(
+    env: &Env,
+    public_key: &BytesN<65>,
+    payload: &Hash<32>,
+    assertion: &WebAuthnAssertion,
+) -> Result<(), AccountError> {
+    let auth_data = &assertion.authenticator_data;
+    if auth_data.len() < AUTH_DATA_MIN_LEN || auth_data.get_unchecked(32) & FLAG_USER_PRESENT == 0 {
+        return Err(AccountError::InvalidAuthenticatorData);
+    }
+
+    let client_data = &assertion.client_data_json;
+    let len = client_data.len();
+    if len > MAX_CLIENT_DATA_LEN {
+        return Err(AccountError::InvalidClientData);
+    }
+    let mut buf = [0u8; MAX_CLIENT_DATA_LEN as usize];
+    let json = &mut buf[..len as usize];
+    client_data.copy_into_slice(json);
+
+    let mut challenge = [0u8; 43];
+    base64url_encode(&payload.to_array(), &mut challenge);
+    if !contains(json, b"\"type\":\"webauthn.get\"") || !contains_challenge(json, &challenge) {
+        return Err(AccountError::InvalidClientData);
+    }
+
+    let mut message = auth_data.clone();
+    message.extend_from_array(&env.crypto().sha256(client_data).to_array());
+    let digest = env.crypto().sha256(&message);
+    env.crypto()
+        .secp256r1_verify(public_key, &digest, &assertion.signature);
+    Ok(())
+}
+
+fn contains_challenge(json: &[u8], challenge: &[u8; 43]) -> bool {
+    const PREFIX: &[u8] = b"\"challenge\":\"";
+    let mut needle = [0u8; PREFIX.len() + 44];
+    needle[..PREFIX.len()].copy_from_slice(PREFIX);
+    needle[PREFIX.len()..PREFIX.len() + 43].copy_from_slice(challenge);
+    needle[PREFIX.len() + 43] = b'"';
+    contains(json, &needle)
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+/// Unpadded base64url, as used for the WebAuthn challenge.
+fn base64url_encode(input: &[u8; 32], out: &mut [u8; 43]) {
+    let mut o = 0;
+    for chunk in input.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..=chunk.len() {
+            out[o] = BASE64URL[((n >> (18 - 6 * i)) & 0x3f) as usize];
+            o += 1;
+        }
+    }
+}
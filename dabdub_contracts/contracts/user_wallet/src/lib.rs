@@ -1,12 +1,131 @@
 #![no_std]
 
+mod account;
+
+pub use account::{
+    AccountError, AccountSignature, SessionKey, SignatureProof, SignerKey, SignerRole,
+    WebAuthnAssertion,
+};
+
 use soroban_sdk::{
-    contract, contractclient, contractevent, contractimpl, contracttype, token, Address, Env,
+    auth::{ContractContext, InvokerContractAuthEntry, SubContractInvocation},
+    contract, contractclient, contractevent, contractimpl, contracttype, token, vec, Address,
+    BytesN, Env, IntoVal, Symbol, Vec,
 };
 
 #[contractclient(name = "CheeseVaultClient")]
 pub trait CheeseVaultTrait {
     fn get_fee_amount(env: Env) -> i128;
+    fn get_payment_fee(env: Env, payer: Address, payment_id: BytesN<32>) -> i128;
+    fn deposit_and_process(
+        env: Env,
+        caller: Address,
+        user_wallet: Address,
+        payment_amount: i128,
+        payment_id: BytesN<32>,
+    );
+    fn process_wallet_payment(
+        env: Env,
+        wallet: Address,
+        recipient: Address,
+        payment_amount: i128,
+        payment_id: BytesN<32>,
+        max_fee: i128,
+    ) -> PaymentReceipt;
+    fn create_schedule(
+        env: Env,
+        payer: Address,
+        recipient: Address,
+        amount: i128,
+        interval_ledgers: u32,
+        max_cycles: u32,
+    ) -> u64;
+    fn pause_schedule(env: Env, schedule_id: u64);
+    fn resume_schedule(env: Env, schedule_id: u64);
+    fn cancel_schedule(env: Env, schedule_id: u64);
+}
+
+#[contractclient(name = "WalletFactoryClient")]
+pub trait WalletFactoryTrait {
+    fn get_backend(env: Env) -> Address;
+    fn get_vault(env: Env) -> Address;
+    fn is_backend(env: Env, account: Address) -> bool;
+    fn wallet_closed(env: Env, wallet: Address);
+}
+
+/// Receipt returned by the vault for a payment funded by this wallet.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PaymentReceipt {
+    pub payment_id: BytesN<32>,
+    pub payer: Address,
+    pub recipient: Address,
+    pub payment_amount: i128,
+    pub fee_amount: i128,
+    pub total_amount: i128,
+    pub expiry_ledger: u32,
+}
+
+/// Limits applied to backend-initiated withdrawals. `daily_cap` is the most
+/// that can leave the wallet within any rolling window of `window_ledgers` ledgers.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpendingLimits {
+    pub per_tx_max: i128,
+    pub daily_cap: i128,
+    pub window_ledgers: u32,
+}
+
+/// A backend spend still counted against the rolling window.
+#[contracttype]
+#[derive(Clone)]
+pub struct SpendRecord {
+    pub ledger: u32,
+    pub amount: i128,
+}
+
+/// How much the backend may do on this wallet. `Restricted` only allows
+/// `transfer_to_vault`; `Revoked` is full self-custody.
+#[contracttype]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BackendMode {
+    Full,
+    Restricted,
+    Revoked,
+}
+
+/// Lifecycle of a wallet. `Frozen` wallets can only be emptied through a
+/// compliance exit approved via the factory; `Closed` is final.
+#[contracttype]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WalletStatus {
+    Active,
+    Frozen,
+    Closed,
+}
+
+/// Guardian-driven owner change, one per proposed owner. Once `approvals`
+/// reach the threshold it can be executed from `executable_ledger` unless the
+/// current owner vetoes it.
+#[contracttype]
+#[derive(Clone)]
+pub struct RecoveryProposal {
+    pub new_owner: Address,
+    pub approvals: Vec<Address>,
+    pub executable_ledger: Option<u32>,
+}
+
+/// Payment the backend committed to settle through `transfer_to_vault`,
+/// which must then match it exactly. Lapses after `expiry_ledger`.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommittedPayment {
+    pub payment_id: BytesN<32>,
+    pub vault: Address,
+    pub recipient: Address,
+    pub payment_amount: i128,
+    pub max_fee: i128,
+    pub expiry_ledger: u32,
 }
 
 #[contracttype]
@@ -16,106 +135,502 @@ pub enum DataKey {
     Owner,
     Vault,
     UsdcToken,
+    SpendingLimits,
+    SpendingWindow, // spends inside the rolling window, oldest first
+    AllowlistEnabled,
+    AllowedRecipient(Address), // recipient -> ledger from which it can be used
+    BackendMode,
+    Guardians,
+    RecoveryThreshold,
+    Recovery, // Vec<RecoveryProposal>, at most one per guardian
+    EmergencyDelay,
+    EmergencyExit, // ledger from which the requested exit can be executed
+    Signer(SignerKey),
+    OwnerPasskey,  // secp256r1 key registered as owner signer by set_passkey_owner
+    PayoutAddress, // where exits pay out while the wallet owns itself
+    Session(SignerKey),
+    SessionKeys,
+    Assets,                 // supported assets, settlement asset first
+    ExecutedOp(BytesN<32>), // temporary: op id -> ledger it executed at
+    Factory,
+    BackendPinned, // owner chose the backend; stop following the factory
+    Version,
+    UpgradeOptOut,
+    Status,
+    ApprovedVault,     // spender of the last `approve_vault` allowance
+    CommittedPayments, // Vec<CommittedPayment>, expired ones pruned on write
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssetBalance {
+    pub asset: Address,
+    pub balance: i128,
 }
 
+/// Ledgers before a newly allowlisted recipient can receive backend withdrawals.
+const ALLOWLIST_DELAY_LEDGERS: u32 = 17_280;
+
+/// Ledgers the owner has to veto a recovery after guardians reach the threshold.
+const RECOVERY_DELAY_LEDGERS: u32 = 51_840;
+
+/// Default and maximum wait between requesting and executing an emergency exit.
+const DEFAULT_EMERGENCY_DELAY_LEDGERS: u32 = 720;
+const MAX_EMERGENCY_DELAY_LEDGERS: u32 = 120_960;
+
+/// How long processed operation ids are remembered (~30 days).
+const EXECUTED_OP_TTL_LEDGERS: u32 = 518_400;
+
+/// How long a committed payment can still be settled (~1 day).
+const COMMITTED_PAYMENT_LEDGERS: u32 = 17_280;
+
+/// Most committed payments outstanding at once.
+const MAX_COMMITTED_PAYMENTS: u32 = 10;
+
+/// Most backend spends tracked inside one rolling window.
+const MAX_WINDOW_SPENDS: u32 = 100;
+
 #[contractevent(topics = ["WALLET", "withdraw"])]
 struct WithdrawalEvent {
+    asset: Address,
     recipient: Address,
     amount: i128,
 }
 
+#[contractevent(topics = ["WALLET", "limits_upd"])]
+struct SpendingLimitsUpdatedEvent {
+    updated_by: Address,
+    per_tx_max: i128,
+    daily_cap: i128,
+    window_ledgers: u32,
+}
+
+#[contractevent(topics = ["WALLET", "limit_hit"])]
+struct SpendingLimitReachedEvent {
+    spent: i128,
+    daily_cap: i128,
+    window_start: u32,
+}
+
+#[contractevent(topics = ["WALLET", "allow_add"])]
+struct RecipientAllowedEvent {
+    recipient: Address,
+    active_from_ledger: u32,
+}
+
+#[contractevent(topics = ["WALLET", "allow_rm"])]
+struct RecipientRemovedEvent {
+    recipient: Address,
+}
+
+#[contractevent(topics = ["WALLET", "allow_mode"])]
+struct AllowlistModeUpdatedEvent {
+    enabled: bool,
+}
+
+#[contractevent(topics = ["WALLET", "mode_upd"])]
+struct BackendModeUpdatedEvent {
+    old_mode: BackendMode,
+    new_mode: BackendMode,
+}
+
+#[contractevent(topics = ["WALLET", "backend_upd"])]
+struct BackendAuthorizedEvent {
+    old_backend: Address,
+    new_backend: Address,
+}
+
+#[contractevent(topics = ["WALLET", "guard_upd"])]
+struct GuardiansUpdatedEvent {
+    guardians: Vec<Address>,
+    threshold: u32,
+}
+
+#[contractevent(topics = ["WALLET", "recov_sup"])]
+struct RecoverySupportedEvent {
+    guardian: Address,
+    new_owner: Address,
+    approvals: u32,
+}
+
+#[contractevent(topics = ["WALLET", "recov_veto"])]
+struct RecoveryVetoedEvent {
+    new_owner: Address,
+}
+
+#[contractevent(topics = ["WALLET", "signer_add"])]
+struct SignerAddedEvent {
+    signer: SignerKey,
+    role: SignerRole,
+}
+
+#[contractevent(topics = ["WALLET", "signer_rm"])]
+struct SignerRemovedEvent {
+    signer: SignerKey,
+}
+
+#[contractevent(topics = ["WALLET", "sess_add"])]
+struct SessionKeyAddedEvent {
+    key: SignerKey,
+    allowed_fns: Vec<Symbol>,
+    spend_cap: i128,
+    expiry_ledger: u32,
+}
+
+#[contractevent(topics = ["WALLET", "sess_rm"])]
+struct SessionKeyRevokedEvent {
+    key: SignerKey,
+}
+
+#[contractevent(topics = ["WALLET", "passkey"])]
+struct PasskeyOwnerSetEvent {
+    public_key: BytesN<65>,
+}
+
+#[contractevent(topics = ["WALLET", "payout_set"])]
+struct PayoutAddressSetEvent {
+    payout: Address,
+}
+
 #[contractevent(topics = ["WALLET", "owner_upd"])]
 struct OwnerUpdatedEvent {
     old_owner: Option<Address>,
     new_owner: Address,
 }
 
+#[contractevent(topics = ["WALLET", "emerg_req"])]
+struct EmergencyExitRequestedEvent {
+    owner: Address,
+    executable_ledger: u32,
+}
+
+#[contractevent(topics = ["WALLET", "emerg_cncl"])]
+struct EmergencyExitCancelledEvent {
+    owner: Address,
+}
+
+#[contractevent(topics = ["WALLET", "committed"])]
+struct PaymentCommittedEvent {
+    payment_id: BytesN<32>,
+    vault: Address,
+    recipient: Address,
+    payment_amount: i128,
+    max_fee: i128,
+}
+
+#[contractevent(topics = ["WALLET", "emerg_dly"])]
+struct EmergencyDelayUpdatedEvent {
+    old_delay: u32,
+    new_delay: u32,
+}
+
 #[contractevent(topics = ["WALLET", "emerg_wd"])]
 struct EmergencyWithdrawalEvent {
+    asset: Address,
     amount: i128,
 }
 
+#[contractevent(topics = ["WALLET", "asset_add"])]
+struct AssetAddedEvent {
+    asset: Address,
+}
+
+#[contractevent(topics = ["WALLET", "asset_rm"])]
+struct AssetRemovedEvent {
+    asset: Address,
+}
+
+#[contractevent(topics = ["WALLET", "upgraded"])]
+struct WalletUpgradedEvent {
+    version: u32,
+    wasm_hash: BytesN<32>,
+}
+
+#[contractevent(topics = ["WALLET", "upg_optout"])]
+struct UpgradeOptOutUpdatedEvent {
+    opted_out: bool,
+}
+
+#[contractevent(topics = ["WALLET", "frozen"])]
+struct FrozenUpdatedEvent {
+    frozen: bool,
+}
+
+#[contractevent(topics = ["WALLET", "comp_exit"])]
+struct ComplianceExitEvent {
+    to: Address,
+    swept: Vec<AssetBalance>,
+}
+
+#[contractevent(topics = ["WALLET", "closed"])]
+struct WalletClosedEvent {
+    owner: Address,
+    swept: Vec<AssetBalance>,
+}
+
 #[contractevent(topics = ["WALLET", "to_vault"])]
 struct TransferredToVaultEvent {
     vault: Address,
+    payment_id: BytesN<32>,
     payment_amount: i128,
     fee_amount: i128,
     total_amount: i128,
 }
 
+#[contractevent(topics = ["WALLET", "vault_appr"])]
+struct VaultAllowanceSetEvent {
+    vault: Address,
+    amount: i128,
+    expiration_ledger: u32,
+}
+
+#[contractevent(topics = ["WALLET", "rescue"])]
+struct TokenRescuedEvent {
+    token: Address,
+    to: Address,
+    amount: i128,
+}
+
 #[contract]
 pub struct UserWallet;
 
 #[contractimpl]
 impl UserWallet {
+    #[allow(clippy::too_many_arguments)]
     pub fn __constructor(
         env: Env,
         backend: Address,
         vault: Address,
         usdc_token: Address,
         owner: Option<Address>,
+        assets: Vec<Address>,
+        factory: Option<Address>,
+        version: u32,
     ) {
         env.storage().instance().set(&DataKey::Backend, &backend);
         env.storage().instance().set(&DataKey::Vault, &vault);
+        if let Some(factory) = factory {
+            env.storage().instance().set(&DataKey::Factory, &factory);
+        }
+        env.storage().instance().set(&DataKey::Version, &version);
         env.storage()
             .instance()
             .set(&DataKey::UsdcToken, &usdc_token);
 
+        let mut supported = Vec::from_array(&env, [usdc_token]);
+        for asset in assets.iter() {
+            if !supported.contains(&asset) {
+                supported.push_back(asset);
+            }
+        }
+        env.storage().instance().set(&DataKey::Assets, &supported);
+
         if let Some(owner_addr) = owner {
             env.storage().instance().set(&DataKey::Owner, &owner_addr);
         }
     }
 
+    /// Balance of the settlement asset
     pub fn get_balance(env: Env) -> i128 {
         let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
         let token_client = token::Client::new(&env, &usdc_token);
         token_client.balance(&env.current_contract_address())
     }
 
-    pub fn withdraw(env: Env, caller: Address, amount: i128, recipient: Address) {
-        let backend: Address = env.storage().instance().get(&DataKey::Backend).unwrap();
+    /// Balances of all supported assets
+    /// Balances of the supported assets, skipping any whose token contract fails
+    pub fn get_balances(env: Env) -> Vec<AssetBalance> {
+        let wallet = env.current_contract_address();
+        let mut balances = Vec::new(&env);
+        for asset in Self::get_assets(env.clone()).iter() {
+            if let Ok(Ok(balance)) = token::Client::new(&env, &asset).try_balance(&wallet) {
+                balances.push_back(AssetBalance { asset, balance });
+            }
+        }
+        balances
+    }
+
+    /// Withdraw the settlement asset
+    pub fn withdraw(
+        env: Env,
+        caller: Address,
+        amount: i128,
+        recipient: Address,
+        op_id: BytesN<32>,
+    ) {
+        let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
+        Self::withdraw_asset(env, caller, usdc_token, amount, recipient, op_id);
+    }
+
+    /// Withdraw a supported asset (backend or owner). The backend can only move
+    /// the settlement asset, within the spending limits and allowlist.
+    pub fn withdraw_asset(
+        env: Env,
+        caller: Address,
+        asset: Address,
+        amount: i128,
+        recipient: Address,
+        op_id: BytesN<32>,
+    ) {
         let owner_opt: Option<Address> = env.storage().instance().get(&DataKey::Owner);
 
-        let is_backend = caller == backend;
+        let is_backend = Self::is_backend(&env, &caller);
         let is_owner = owner_opt.is_some_and(|owner| caller == owner);
 
         if !is_backend && !is_owner {
             panic!("Not authorized");
         }
+        if !is_owner {
+            Self::require_backend_mode(&env, BackendMode::Full);
+        }
 
         caller.require_auth();
+        Self::require_active(&env);
+        if !is_owner {
+            Self::require_no_emergency_exit(&env);
+        }
 
         if amount <= 0 {
             panic!("Amount must be > 0");
         }
+        if !Self::is_asset_supported(env.clone(), asset.clone()) {
+            panic!("Asset not supported");
+        }
+        Self::record_op(&env, &op_id);
 
-        let balance = Self::get_balance(env.clone());
+        let token_client = token::Client::new(&env, &asset);
+        let balance = token_client.balance(&env.current_contract_address());
         if balance < amount {
             panic!("Insufficient balance");
         }
 
-        let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
-        let token_client = token::Client::new(&env, &usdc_token);
+        // Owner withdrawals are unrestricted; the backend may only move the
+        // settlement asset, within the limits.
+        if !is_owner {
+            let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
+            if asset != usdc_token {
+                panic!("Backend can only withdraw settlement asset");
+            }
+            if !Self::is_recipient_allowed(env.clone(), recipient.clone()) {
+                panic!("Recipient not allowlisted");
+            }
+            Self::consume_spending_limit(&env, amount);
+        }
+
         token_client.transfer(&env.current_contract_address(), &recipient, &amount);
 
         WithdrawalEvent {
+            asset,
             recipient: recipient.clone(),
             amount,
         }
         .publish(&env);
     }
 
-    pub fn set_owner(env: Env, caller: Address, new_owner: Address) {
-        let backend: Address = env.storage().instance().get(&DataKey::Backend).unwrap();
+    /// Configure backend withdrawal limits (backend or owner). The backend can
+    /// only tighten the current limits; raising them needs the owner.
+    pub fn set_spending_limits(
+        env: Env,
+        caller: Address,
+        limits: SpendingLimits,
+        op_id: BytesN<32>,
+    ) {
+        Self::require_backend_or_owner(&env, &caller);
+        caller.require_auth();
+        Self::record_op(&env, &op_id);
+
+        if limits.per_tx_max <= 0 || limits.daily_cap <= 0 {
+            panic!("Limits must be > 0");
+        }
+        if limits.window_ledgers == 0 {
+            panic!("Window must be > 0");
+        }
+
+        let owner_opt: Option<Address> = env.storage().instance().get(&DataKey::Owner);
+        let is_owner = owner_opt.is_some_and(|owner| caller == owner);
+        if !is_owner {
+            let current: Option<SpendingLimits> =
+                env.storage().instance().get(&DataKey::SpendingLimits);
+            if let Some(current) = current {
+                if limits.per_tx_max > current.per_tx_max
+                    || limits.daily_cap > current.daily_cap
+                    || limits.window_ledgers < current.window_ledgers
+                {
+                    panic!("Only owner can raise limits");
+                }
+            }
+        }
+
+        env.storage()
+            .instance()
+            .set(&DataKey::SpendingLimits, &limits);
+
+        SpendingLimitsUpdatedEvent {
+            updated_by: caller,
+            per_tx_max: limits.per_tx_max,
+            daily_cap: limits.daily_cap,
+            window_ledgers: limits.window_ledgers,
+        }
+        .publish(&env);
+    }
+
+    /// Add a backend withdrawal destination (owner only). It becomes usable
+    /// after `ALLOWLIST_DELAY_LEDGERS`.
+    pub fn add_allowed_recipient(env: Env, caller: Address, recipient: Address) {
+        Self::require_owner(&env, &caller);
+        caller.require_auth();
+
+        let active_from_ledger = env
+            .ledger()
+            .sequence()
+            .saturating_add(ALLOWLIST_DELAY_LEDGERS);
+        env.storage().instance().set(
+            &DataKey::AllowedRecipient(recipient.clone()),
+            &active_from_ledger,
+        );
+
+        RecipientAllowedEvent {
+            recipient,
+            active_from_ledger,
+        }
+        .publish(&env);
+    }
+
+    /// Remove a backend withdrawal destination (owner only)
+    pub fn remove_allowed_recipient(env: Env, caller: Address, recipient: Address) {
+        Self::require_owner(&env, &caller);
+        caller.require_auth();
+
+        env.storage()
+            .instance()
+            .remove(&DataKey::AllowedRecipient(recipient.clone()));
+
+        RecipientRemovedEvent { recipient }.publish(&env);
+    }
+
+    /// Restrict backend withdrawals to allowlisted recipients (owner only)
+    pub fn set_allowlist_enabled(env: Env, caller: Address, enabled: bool) {
+        Self::require_owner(&env, &caller);
+        caller.require_auth();
+
+        env.storage()
+            .instance()
+            .set(&DataKey::AllowlistEnabled, &enabled);
+
+        AllowlistModeUpdatedEvent { enabled }.publish(&env);
+    }
 
-        if caller != backend {
+    pub fn set_owner(env: Env, caller: Address, new_owner: Address, op_id: BytesN<32>) {
+        if !Self::is_backend(&env, &caller) {
             panic!("Only backend");
         }
+        Self::require_backend_mode(&env, BackendMode::Full);
 
         caller.require_auth();
+        Self::require_no_emergency_exit(&env);
+        Self::record_op(&env, &op_id);
 
-        let old_owner: Option<Address> = env.storage().instance().get(&DataKey::Owner);
-        env.storage().instance().set(&DataKey::Owner, &new_owner);
+        let old_owner = Self::replace_owner(&env, &new_owner);
 
         OwnerUpdatedEvent {
             old_owner,
@@ -124,89 +639,1398 @@ impl UserWallet {
         .publish(&env);
     }
 
-    pub fn emergency_withdraw(env: Env, caller: Address) {
-        let owner: Address = env
-            .storage()
+    /// Make a secp256r1 passkey the wallet owner. The wallet becomes its own
+    /// owner and owner calls are authorized through `__check_auth` with
+    /// WebAuthn assertions from that key. The backend may only do this while
+    /// no owner is set; afterwards the current owner must rotate the key.
+    pub fn set_passkey_owner(env: Env, caller: Address, public_key: BytesN<65>, op_id: BytesN<32>) {
+        if env.storage().instance().has(&DataKey::Owner) {
+            Self::require_owner(&env, &caller);
+        } else {
+            if !Self::is_backend(&env, &caller) {
+                panic!("Only backend");
+            }
+            Self::require_backend_mode(&env, BackendMode::Full);
+        }
+        caller.require_auth();
+        Self::record_op(&env, &op_id);
+
+        let wallet = env.current_contract_address();
+        let old_owner = Self::replace_owner(&env, &wallet);
+        env.storage().instance().set(
+            &DataKey::Signer(SignerKey::Secp256r1(public_key.clone())),
+            &SignerRole::Owner,
+        );
+        env.storage()
             .instance()
-            .get(&DataKey::Owner)
-            .expect("Owner not set");
+            .set(&DataKey::OwnerPasskey, &public_key);
 
-        if caller != owner {
-            panic!("Only owner");
+        PasskeyOwnerSetEvent { public_key }.publish(&env);
+        OwnerUpdatedEvent {
+            old_owner,
+            new_owner: wallet,
         }
+        .publish(&env);
+    }
 
+    /// Set where `emergency_withdraw` and `close` send funds while the owner
+    /// is a passkey and the wallet owns itself (owner only)
+    pub fn set_payout_address(env: Env, caller: Address, payout: Address) {
+        Self::require_owner(&env, &caller);
         caller.require_auth();
 
-        let balance = Self::get_balance(env.clone());
-        if balance <= 0 {
-            panic!("No balance to withdraw");
+        if payout == env.current_contract_address() {
+            panic!("Invalid payout address");
         }
+        env.storage()
+            .instance()
+            .set(&DataKey::PayoutAddress, &payout);
 
-        let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
-        let token_client = token::Client::new(&env, &usdc_token);
-        token_client.transfer(&env.current_contract_address(), &owner, &balance);
-
-        EmergencyWithdrawalEvent { amount: balance }.publish(&env);
+        PayoutAddressSetEvent { payout }.publish(&env);
     }
 
-    /// Transfer USDC to vault for payment processing (backend or vault only)
-    pub fn transfer_to_vault(env: Env, caller: Address, payment_amount: i128) -> i128 {
-        if payment_amount <= 0 {
-            panic!("Payment amount must be > 0");
+    /// Register a key that can sign for this wallet as a custom account (owner only)
+    pub fn add_signer(env: Env, caller: Address, signer: SignerKey, role: SignerRole) {
+        Self::require_owner(&env, &caller);
+        caller.require_auth();
+
+        if env
+            .storage()
+            .instance()
+            .has(&DataKey::Session(signer.clone()))
+        {
+            panic!("Key is a session key");
         }
 
-        let backend: Address = env.storage().instance().get(&DataKey::Backend).unwrap();
-        let vault: Address = env.storage().instance().get(&DataKey::Vault).unwrap();
+        env.storage()
+            .instance()
+            .set(&DataKey::Signer(signer.clone()), &role);
 
-        if caller != backend && caller != vault {
-            panic!("Not authorized");
+        SignerAddedEvent { signer, role }.publish(&env);
+    }
+
+    /// Remove an account signer (owner only)
+    pub fn remove_signer(env: Env, caller: Address, signer: SignerKey) {
+        Self::require_owner(&env, &caller);
+        caller.require_auth();
+
+        if !env
+            .storage()
+            .instance()
+            .has(&DataKey::Signer(signer.clone()))
+        {
+            panic!("Signer not found");
         }
+        let owner_passkey: Option<BytesN<65>> =
+            env.storage().instance().get(&DataKey::OwnerPasskey);
+        if owner_passkey.is_some_and(|key| signer == SignerKey::Secp256r1(key)) {
+            panic!("Cannot remove owner passkey");
+        }
+        env.storage()
+            .instance()
+            .remove(&DataKey::Signer(signer.clone()));
+
+        SignerRemovedEvent { signer }.publish(&env);
+    }
 
-        // Backend and vault calls must be authorized by the caller.
+    /// Grant a session key scoped to `allowed_fns` on this wallet, a cumulative
+    /// `spend_cap` and an expiry ledger (owner only). Re-adding a key replaces
+    /// its scope and resets what it has spent.
+    pub fn add_session_key(
+        env: Env,
+        caller: Address,
+        key: SignerKey,
+        allowed_fns: Vec<Symbol>,
+        spend_cap: i128,
+        expiry_ledger: u32,
+    ) {
+        Self::require_owner(&env, &caller);
         caller.require_auth();
 
-        let vault_client = CheeseVaultClient::new(&env, &vault);
-        let fee_amount = vault_client.get_fee_amount();
-        if fee_amount < 0 {
-            panic!("Invalid fee");
+        if allowed_fns.is_empty() {
+            panic!("No functions allowed");
+        }
+        if spend_cap < 0 {
+            panic!("Spend cap must be >= 0");
+        }
+        if expiry_ledger <= env.ledger().sequence() {
+            panic!("Expiry must be in the future");
+        }
+        if env.storage().instance().has(&DataKey::Signer(key.clone())) {
+            panic!("Key is already a signer");
         }
 
-        let total_amount = payment_amount
-            .checked_add(fee_amount)
-            .expect("Amount overflow");
+        let mut keys = Self::get_session_keys_list(&env);
+        if !keys.contains(&key) {
+            keys.push_back(key.clone());
+            env.storage().instance().set(&DataKey::SessionKeys, &keys);
+        }
+        let session = SessionKey {
+            key: key.clone(),
+            allowed_fns: allowed_fns.clone(),
+            spend_cap,
+            spent: 0,
+            expiry_ledger,
+        };
+        env.storage()
+            .instance()
+            .set(&DataKey::Session(key.clone()), &session);
 
-        let balance = Self::get_balance(env.clone());
-        if balance < total_amount {
-            panic!("Insufficient balance");
+        SessionKeyAddedEvent {
+            key,
+            allowed_fns,
+            spend_cap,
+            expiry_ledger,
         }
+        .publish(&env);
+    }
 
-        let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
-        let token_client = token::Client::new(&env, &usdc_token);
-        token_client.transfer(&env.current_contract_address(), &vault, &total_amount);
+    /// Revoke a session key (owner only)
+    pub fn revoke_session_key(env: Env, caller: Address, key: SignerKey) {
+        Self::require_owner(&env, &caller);
+        caller.require_auth();
 
-        TransferredToVaultEvent {
-            vault: vault.clone(),
-            payment_amount,
-            fee_amount,
-            total_amount,
+        let mut keys = Self::get_session_keys_list(&env);
+        let index = keys
+            .first_index_of(&key)
+            .unwrap_or_else(|| panic!("Session key not found"));
+        keys.remove(index);
+        env.storage().instance().set(&DataKey::SessionKeys, &keys);
+        env.storage()
+            .instance()
+            .remove(&DataKey::Session(key.clone()));
+
+        SessionKeyRevokedEvent { key }.publish(&env);
+    }
+
+    /// Replace this wallet's code (deploying factory only). Skipped by the
+    /// factory for wallets whose owner opted out.
+    pub fn upgrade(env: Env, new_wasm_hash: BytesN<32>, version: u32) {
+        Self::require_factory(&env);
+
+        if Self::is_upgrade_opted_out(env.clone()) {
+            panic!("Wallet opted out of upgrades");
+        }
+        if version <= Self::get_version(env.clone()) {
+            panic!("Version must increase");
+        }
+
+        env.storage().instance().set(&DataKey::Version, &version);
+        WalletUpgradedEvent {
+            version,
+            wasm_hash: new_wasm_hash.clone(),
         }
         .publish(&env);
 
-        total_amount
+        env.deployer().update_current_contract_wasm(new_wasm_hash);
     }
 
-    // View functions
-    pub fn get_backend(env: Env) -> Address {
-        env.storage().instance().get(&DataKey::Backend).unwrap()
+    /// Freeze or unfreeze the wallet (deploying factory only). Freezing blocks
+    /// every outgoing transfer and revokes the vault allowance; the backend has
+    /// to `approve_vault` again after unfreezing.
+    pub fn set_frozen(env: Env, frozen: bool) {
+        Self::require_factory(&env);
+
+        let status = match Self::get_status(env.clone()) {
+            WalletStatus::Closed => panic!("Wallet is closed"),
+            _ if frozen => WalletStatus::Frozen,
+            _ => WalletStatus::Active,
+        };
+        env.storage().instance().set(&DataKey::Status, &status);
+        if frozen {
+            Self::revoke_vault_allowance(&env);
+        }
+
+        FrozenUpdatedEvent { frozen }.publish(&env);
     }
 
-    pub fn get_owner(env: Env) -> Option<Address> {
-        env.storage().instance().get(&DataKey::Owner)
+    /// Move every supported asset of a frozen wallet to `to`, as approved by
+    /// compliance through the deploying factory. The wallet stays frozen.
+    pub fn compliance_exit(env: Env, to: Address) {
+        Self::require_factory(&env);
+
+        if Self::get_status(env.clone()) != WalletStatus::Frozen {
+            panic!("Wallet is not frozen");
+        }
+
+        let swept = Self::sweep(&env, &to);
+        ComplianceExitEvent { to, swept }.publish(&env);
     }
 
-    pub fn get_vault(env: Env) -> Address {
-        env.storage().instance().get(&DataKey::Vault).unwrap()
+    /// Close the wallet for good, sending every supported asset to the owner
+    /// (owner only). The deploying factory is told so it can give the user a
+    /// new wallet.
+    pub fn close(env: Env, caller: Address) {
+        Self::require_owner(&env, &caller);
+        caller.require_auth();
+        Self::require_active(&env);
+
+        let swept = Self::sweep(&env, &Self::payout_address(&env, &caller));
+        Self::revoke_vault_allowance(&env);
+        env.storage()
+            .instance()
+            .set(&DataKey::Status, &WalletStatus::Closed);
+
+        let factory: Option<Address> = env.storage().instance().get(&DataKey::Factory);
+        if let Some(factory) = factory {
+            WalletFactoryClient::new(&env, &factory).wallet_closed(&env.current_contract_address());
+        }
+
+        WalletClosedEvent {
+            owner: caller,
+            swept,
+        }
+        .publish(&env);
+    }
+
+    /// Opt out of (or back into) factory upgrades (owner only)
+    pub fn set_upgrade_opt_out(env: Env, caller: Address, opted_out: bool) {
+        Self::require_owner(&env, &caller);
+        caller.require_auth();
+
+        env.storage()
+            .instance()
+            .set(&DataKey::UpgradeOptOut, &opted_out);
+
+        UpgradeOptOutUpdatedEvent { opted_out }.publish(&env);
+    }
+
+    /// Rotate the owner key (owner only)
+    pub fn rotate_owner(env: Env, caller: Address, new_owner: Address) {
+        Self::require_owner(&env, &caller);
+        caller.require_auth();
+
+        Self::replace_owner(&env, &new_owner);
+
+        OwnerUpdatedEvent {
+            old_owner: Some(caller),
+            new_owner,
+        }
+        .publish(&env);
+    }
+
+    /// Restrict or revoke the backend's powers, or restore them (owner only)
+    pub fn set_backend_mode(env: Env, caller: Address, mode: BackendMode) {
+        Self::require_owner(&env, &caller);
+        caller.require_auth();
+
+        let old_mode = Self::get_backend_mode(env.clone());
+        env.storage().instance().set(&DataKey::BackendMode, &mode);
+
+        BackendModeUpdatedEvent {
+            old_mode,
+            new_mode: mode,
+        }
+        .publish(&env);
+    }
+
+    /// Authorize a (new) backend with full powers, leaving self-custody (owner only)
+    pub fn authorize_backend(env: Env, caller: Address, new_backend: Address) {
+        Self::require_owner(&env, &caller);
+        caller.require_auth();
+
+        let old_backend = Self::backend(&env);
+        env.storage()
+            .instance()
+            .set(&DataKey::Backend, &new_backend);
+        env.storage().instance().set(&DataKey::BackendPinned, &true);
+
+        let old_mode = Self::get_backend_mode(env.clone());
+        env.storage()
+            .instance()
+            .set(&DataKey::BackendMode, &BackendMode::Full);
+
+        BackendAuthorizedEvent {
+            old_backend,
+            new_backend,
+        }
+        .publish(&env);
+
+        if old_mode != BackendMode::Full {
+            BackendModeUpdatedEvent {
+                old_mode,
+                new_mode: BackendMode::Full,
+            }
+            .publish(&env);
+        }
+    }
+
+    /// Register recovery guardians and how many must agree (owner only)
+    pub fn set_guardians(env: Env, caller: Address, guardians: Vec<Address>, threshold: u32) {
+        Self::require_owner(&env, &caller);
+        caller.require_auth();
+
+        if threshold == 0 || threshold > guardians.len() {
+            panic!("Invalid threshold");
+        }
+        for (i, guardian) in guardians.iter().enumerate() {
+            if guardians.first_index_of(&guardian) != Some(i as u32) {
+                panic!("Duplicate guardian");
+            }
+        }
+
+        env.storage()
+            .instance()
+            .set(&DataKey::Guardians, &guardians);
+        env.storage()
+            .instance()
+            .set(&DataKey::RecoveryThreshold, &threshold);
+        // Any recovery in progress was approved by the old guardian set.
+        env.storage().instance().remove(&DataKey::Recovery);
+
+        GuardiansUpdatedEvent {
+            guardians,
+            threshold,
+        }
+        .publish(&env);
+    }
+
+    /// Propose or second a change of owner (guardian only). Each guardian backs
+    /// one proposed owner at a time; backing another moves their approval. When
+    /// a proposal reaches the threshold it becomes executable after
+    /// `RECOVERY_DELAY_LEDGERS` and replaces any other executable proposal.
+    pub fn support_recovery(env: Env, guardian: Address, new_owner: Address) {
+        let guardians: Vec<Address> = env
+            .storage()
+            .instance()
+            .get(&DataKey::Guardians)
+            .unwrap_or(Vec::new(&env));
+        if !guardians.contains(&guardian) {
+            panic!("Only guardian");
+        }
+
+        guardian.require_auth();
+
+        let threshold: u32 = env
+            .storage()
+            .instance()
+            .get(&DataKey::RecoveryThreshold)
+            .unwrap();
+        let existing = Self::get_recovery_proposals(env.clone());
+
+        let mut proposal = RecoveryProposal {
+            new_owner: new_owner.clone(),
+            approvals: Vec::new(&env),
+            executable_ledger: None,
+        };
+        let mut others: Vec<RecoveryProposal> = Vec::new(&env);
+        for mut p in existing.iter() {
+            if p.new_owner == new_owner {
+                if p.approvals.contains(&guardian) {
+                    panic!("Already approved");
+                }
+                proposal = p;
+                continue;
+            }
+            // Move this guardian's approval away from any other proposal.
+            if let Some(index) = p.approvals.first_index_of(&guardian) {
+                p.approvals.remove(index);
+                if p.approvals.len() < threshold {
+                    p.executable_ledger = None;
+                }
+            }
+            if !p.approvals.is_empty() {
+                others.push_back(p);
+            }
+        }
+
+        proposal.approvals.push_back(guardian.clone());
+        if proposal.executable_ledger.is_none() && proposal.approvals.len() >= threshold {
+            proposal.executable_ledger = Some(
+                env.ledger()
+                    .sequence()
+                    .saturating_add(RECOVERY_DELAY_LEDGERS),
+            );
+            // A threshold of guardians overrides any other executable proposal.
+            for i in 0..others.len() {
+                let mut p = others.get(i).unwrap();
+                p.executable_ledger = None;
+                others.set(i, p);
+            }
+        }
+
+        let approvals = proposal.approvals.len();
+        others.push_back(proposal);
+        env.storage().instance().set(&DataKey::Recovery, &others);
+
+        RecoverySupportedEvent {
+            guardian,
+            new_owner,
+            approvals,
+        }
+        .publish(&env);
+    }
+
+    /// Veto every pending recovery proposal (owner only)
+    pub fn veto_recovery(env: Env, caller: Address) {
+        Self::require_owner(&env, &caller);
+        caller.require_auth();
+
+        let proposals = Self::get_recovery_proposals(env.clone());
+        if proposals.is_empty() {
+            panic!("No recovery in progress");
+        }
+        env.storage().instance().remove(&DataKey::Recovery);
+
+        for proposal in proposals.iter() {
+            RecoveryVetoedEvent {
+                new_owner: proposal.new_owner,
+            }
+            .publish(&env);
+        }
+    }
+
+    /// Apply the approved recovery once its veto window has passed (anyone)
+    pub fn execute_recovery(env: Env) {
+        let proposals = Self::get_recovery_proposals(env.clone());
+        if proposals.is_empty() {
+            panic!("No recovery in progress");
+        }
+
+        let proposal = proposals
+            .iter()
+            .find(|p| p.executable_ledger.is_some())
+            .unwrap_or_else(|| panic!("Recovery threshold not reached"));
+        let executable_ledger = proposal.executable_ledger.unwrap();
+        if env.ledger().sequence() < executable_ledger {
+            panic!("Recovery delay not elapsed");
+        }
+
+        let old_owner = Self::replace_owner(&env, &proposal.new_owner);
+        env.storage().instance().remove(&DataKey::Recovery);
+
+        OwnerUpdatedEvent {
+            old_owner,
+            new_owner: proposal.new_owner,
+        }
+        .publish(&env);
+    }
+
+    /// Start the emergency exit (owner only). Until the delay elapses the backend
+    /// can only settle payments committed before the request.
+    pub fn request_emergency_withdraw(env: Env, caller: Address) -> u32 {
+        Self::require_owner(&env, &caller);
+        caller.require_auth();
+
+        if env.storage().instance().has(&DataKey::EmergencyExit) {
+            panic!("Emergency exit already requested");
+        }
+
+        let executable_ledger = env
+            .ledger()
+            .sequence()
+            .saturating_add(Self::get_emergency_delay(env.clone()));
+        env.storage()
+            .instance()
+            .set(&DataKey::EmergencyExit, &executable_ledger);
+
+        EmergencyExitRequestedEvent {
+            owner: caller,
+            executable_ledger,
+        }
+        .publish(&env);
+
+        executable_ledger
+    }
+
+    /// Withdraw a pending emergency exit request (owner only)
+    pub fn cancel_emergency_withdraw(env: Env, caller: Address) {
+        Self::require_owner(&env, &caller);
+        caller.require_auth();
+
+        if !env.storage().instance().has(&DataKey::EmergencyExit) {
+            panic!("No emergency exit requested");
+        }
+        env.storage().instance().remove(&DataKey::EmergencyExit);
+
+        EmergencyExitCancelledEvent { owner: caller }.publish(&env);
+    }
+
+    /// Set the emergency exit delay in ledgers (owner only)
+    pub fn set_emergency_delay(env: Env, caller: Address, delay_ledgers: u32, op_id: BytesN<32>) {
+        Self::require_owner(&env, &caller);
+        caller.require_auth();
+        Self::record_op(&env, &op_id);
+
+        if delay_ledgers > MAX_EMERGENCY_DELAY_LEDGERS {
+            panic!("Delay exceeds maximum");
+        }
+
+        let old_delay = Self::get_emergency_delay(env.clone());
+        env.storage()
+            .instance()
+            .set(&DataKey::EmergencyDelay, &delay_ledgers);
+
+        EmergencyDelayUpdatedEvent {
+            old_delay,
+            new_delay: delay_ledgers,
+        }
+        .publish(&env);
+    }
+
+    /// Complete a requested emergency exit once its delay has elapsed,
+    /// sending the full balance to the owner or its payout address (owner only)
+    pub fn emergency_withdraw(env: Env, caller: Address) {
+        let owner: Address = env
+            .storage()
+            .instance()
+            .get(&DataKey::Owner)
+            .expect("Owner not set");
+
+        if caller != owner {
+            panic!("Only owner");
+        }
+
+        caller.require_auth();
+        Self::require_active(&env);
+
+        let executable_ledger: u32 = env
+            .storage()
+            .instance()
+            .get(&DataKey::EmergencyExit)
+            .expect("No emergency exit requested");
+        if env.ledger().sequence() < executable_ledger {
+            panic!("Emergency delay not elapsed");
+        }
+        env.storage().instance().remove(&DataKey::EmergencyExit);
+
+        // Sweep every supported asset to the owner.
+        let swept = Self::sweep(&env, &Self::payout_address(&env, &owner));
+        if swept.is_empty() {
+            panic!("No balance to withdraw");
+        }
+        for AssetBalance { asset, balance } in swept.iter() {
+            EmergencyWithdrawalEvent {
+                asset,
+                amount: balance,
+            }
+            .publish(&env);
+        }
+    }
+
+    /// Support an additional asset (owner only)
+    pub fn add_asset(env: Env, caller: Address, asset: Address, op_id: BytesN<32>) {
+        Self::require_owner(&env, &caller);
+        caller.require_auth();
+        Self::record_op(&env, &op_id);
+
+        let mut assets = Self::get_assets(env.clone());
+        if assets.contains(&asset) {
+            panic!("Asset already supported");
+        }
+        assets.push_back(asset.clone());
+        env.storage().instance().set(&DataKey::Assets, &assets);
+
+        AssetAddedEvent { asset }.publish(&env);
+    }
+
+    /// Stop supporting an asset (owner only). The settlement asset
+    /// cannot be removed.
+    pub fn remove_asset(env: Env, caller: Address, asset: Address) {
+        Self::require_owner(&env, &caller);
+        caller.require_auth();
+
+        let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
+        if asset == usdc_token {
+            panic!("Cannot remove settlement asset");
+        }
+
+        let mut assets = Self::get_assets(env.clone());
+        let index = assets
+            .first_index_of(&asset)
+            .unwrap_or_else(|| panic!("Asset not supported"));
+        assets.remove(index);
+        env.storage().instance().set(&DataKey::Assets, &assets);
+
+        AssetRemovedEvent { asset }.publish(&env);
+    }
+
+    /// Commit to settling `payment_id` through `transfer_to_vault` with exactly
+    /// these terms to the current vault (backend only). Committed payments can
+    /// still be settled while an emergency exit is pending. Takes no separate
+    /// op id: a retry is rejected as already committed, and `payment_id` is
+    /// recorded as the op id once `transfer_to_vault` settles it.
+    pub fn commit_payment(
+        env: Env,
+        caller: Address,
+        payment_id: BytesN<32>,
+        recipient: Address,
+        payment_amount: i128,
+        max_fee: i128,
+    ) {
+        if !Self::is_backend(&env, &caller) {
+            panic!("Only backend");
+        }
+        Self::require_backend_mode(&env, BackendMode::Restricted);
+
+        caller.require_auth();
+        Self::require_active(&env);
+        Self::require_no_emergency_exit(&env);
+
+        if payment_amount <= 0 {
+            panic!("Payment amount must be > 0");
+        }
+
+        let mut commitments = Self::live_commitments(&env);
+        if commitments.iter().any(|c| c.payment_id == payment_id) {
+            panic!("Payment already committed");
+        }
+        if commitments.len() >= MAX_COMMITTED_PAYMENTS {
+            panic!("Too many committed payments");
+        }
+
+        let vault = Self::vault(&env);
+        commitments.push_back(CommittedPayment {
+            payment_id: payment_id.clone(),
+            vault: vault.clone(),
+            recipient: recipient.clone(),
+            payment_amount,
+            max_fee,
+            expiry_ledger: env
+                .ledger()
+                .sequence()
+                .saturating_add(COMMITTED_PAYMENT_LEDGERS),
+        });
+        env.storage()
+            .instance()
+            .set(&DataKey::CommittedPayments, &commitments);
+
+        PaymentCommittedEvent {
+            payment_id,
+            vault,
+            recipient,
+            payment_amount,
+            max_fee,
+        }
+        .publish(&env);
+    }
+
+    /// Pay `payment_amount` plus the vault fee into the vault and have it book
+    /// the payment for `recipient` under `payment_id` in the same call
+    /// (backend or self). Aborts if the vault fee is above `max_fee`, normally
+    /// the fee quoted to the user. `payment_id` is also the operation id checked
+    /// by `was_executed`. The vault settles in the settlement asset only.
+    pub fn transfer_to_vault(
+        env: Env,
+        caller: Address,
+        payment_amount: i128,
+        payment_id: BytesN<32>,
+        recipient: Address,
+        max_fee: i128,
+    ) -> PaymentReceipt {
+        if payment_amount <= 0 {
+            panic!("Payment amount must be > 0");
+        }
+
+        let is_backend = Self::is_backend(&env, &caller);
+        let vault = Self::vault(&env);
+
+        // The wallet itself may call through its account signers (see `__check_auth`).
+        let wallet = env.current_contract_address();
+        if !is_backend && caller != wallet {
+            panic!("Not authorized");
+        }
+        if is_backend {
+            Self::require_backend_mode(&env, BackendMode::Restricted);
+        }
+
+        // Backend and self calls must be authorized by the caller.
+        caller.require_auth();
+        Self::require_active(&env);
+
+        // A pending emergency exit only lets payments committed before it through,
+        // and a committed payment must be settled on exactly the committed terms.
+        let mut commitments = Self::live_commitments(&env);
+        match commitments.iter().position(|c| c.payment_id == payment_id) {
+            Some(index) => {
+                let commitment = commitments.get_unchecked(index as u32);
+                if commitment.vault != vault
+                    || commitment.recipient != recipient
+                    || commitment.payment_amount != payment_amount
+                    || commitment.max_fee != max_fee
+                {
+                    panic!("Payment does not match commitment");
+                }
+                commitments.remove(index as u32);
+                env.storage()
+                    .instance()
+                    .set(&DataKey::CommittedPayments, &commitments);
+            }
+            None => Self::require_no_emergency_exit(&env),
+        }
+        Self::record_op(&env, &payment_id);
+
+        let vault_client = CheeseVaultClient::new(&env, &vault);
+        let fee_amount = vault_client.get_payment_fee(&env.current_contract_address(), &payment_id);
+        if fee_amount < 0 {
+            panic!("Invalid fee");
+        }
+        if fee_amount > max_fee {
+            panic!("Fee exceeds max_fee");
+        }
+
+        let total_amount = payment_amount
+            .checked_add(fee_amount)
+            .expect("Amount overflow");
+
+        // Backend payments go through the same allowlist and limits as withdrawals.
+        if is_backend {
+            if !Self::is_recipient_allowed(env.clone(), recipient.clone()) {
+                panic!("Recipient not allowlisted");
+            }
+            Self::consume_spending_limit(&env, total_amount);
+        }
+
+        let balance = Self::get_balance(env.clone());
+        if balance < total_amount {
+            panic!("Insufficient balance");
+        }
+
+        // Let the vault pull exactly this payment from the wallet.
+        let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
+        env.authorize_as_current_contract(vec![
+            &env,
+            InvokerContractAuthEntry::Contract(SubContractInvocation {
+                context: ContractContext {
+                    contract: usdc_token,
+                    fn_name: Symbol::new(&env, "transfer"),
+                    args: (wallet.clone(), vault.clone(), total_amount).into_val(&env),
+                },
+                sub_invocations: vec![&env],
+            }),
+        ]);
+        let receipt = vault_client.process_wallet_payment(
+            &wallet,
+            &recipient,
+            &payment_amount,
+            &payment_id,
+            &max_fee,
+        );
+
+        TransferredToVaultEvent {
+            vault: vault.clone(),
+            payment_id,
+            payment_amount,
+            fee_amount: receipt.fee_amount,
+            total_amount: receipt.total_amount,
+        }
+        .publish(&env);
+
+        receipt
+    }
+
+    /// Let `operator` pull `payment_amount` plus the vault fee through the
+    /// vault allowance and book it under `payment_id` (backend or owner). The
+    /// wallet authorizes the vault's `deposit_and_process` by calling it, so
+    /// backend calls stay within the spending limits. `payment_id` is also the
+    /// operation id checked by `was_executed`.
+    pub fn deposit_to_vault(
+        env: Env,
+        caller: Address,
+        operator: Address,
+        payment_amount: i128,
+        payment_id: BytesN<32>,
+    ) {
+        let owner_opt: Option<Address> = env.storage().instance().get(&DataKey::Owner);
+        let is_backend = Self::is_backend(&env, &caller);
+        let is_owner = owner_opt.is_some_and(|owner| caller == owner);
+        if !is_backend && !is_owner {
+            panic!("Not authorized");
+        }
+        if is_backend {
+            Self::require_backend_mode(&env, BackendMode::Restricted);
+        }
+
+        caller.require_auth();
+        Self::require_active(&env);
+        if is_backend {
+            Self::require_no_emergency_exit(&env);
+        }
+        Self::record_op(&env, &payment_id);
+
+        let vault = Self::vault(&env);
+        let vault_client = CheeseVaultClient::new(&env, &vault);
+        if is_backend {
+            let total_amount = payment_amount
+                .checked_add(vault_client.get_fee_amount())
+                .expect("Amount overflow");
+            Self::consume_spending_limit(&env, total_amount);
+        }
+
+        vault_client.deposit_and_process(
+            &operator,
+            &env.current_contract_address(),
+            &payment_amount,
+            &payment_id,
+        );
+    }
+
+    /// Recover an unsupported token sent to this wallet by mistake (owner or
+    /// backend). The backend may only send it to the owner's payout address,
+    /// so it cannot use a rescue to move funds elsewhere.
+    pub fn rescue_token(
+        env: Env,
+        caller: Address,
+        token: Address,
+        to: Address,
+        amount: i128,
+        op_id: BytesN<32>,
+    ) {
+        Self::require_backend_or_owner(&env, &caller);
+        caller.require_auth();
+        Self::require_active(&env);
+        Self::record_op(&env, &op_id);
+
+        let owner: Address = env
+            .storage()
+            .instance()
+            .get(&DataKey::Owner)
+            .expect("Owner not set");
+        if caller != owner && to != Self::payout_address(&env, &owner) {
+            panic!("Backend can only rescue to owner");
+        }
+
+        if amount <= 0 {
+            panic!("Amount must be > 0");
+        }
+
+        let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
+        if token == usdc_token {
+            panic!("Cannot rescue settlement asset");
+        }
+        if Self::is_asset_supported(env.clone(), token.clone()) {
+            panic!("Cannot rescue supported asset");
+        }
+
+        let token_client = token::Client::new(&env, &token);
+        token_client.transfer(&env.current_contract_address(), &to, &amount);
+
+        TokenRescuedEvent { token, to, amount }.publish(&env);
+    }
+
+    /// Let the vault pull up to `amount` USDC until `expiration_ledger`,
+    /// used by recurring schedules (owner only)
+    pub fn approve_vault(
+        env: Env,
+        caller: Address,
+        amount: i128,
+        expiration_ledger: u32,
+        op_id: BytesN<32>,
+    ) {
+        Self::require_owner(&env, &caller);
+        caller.require_auth();
+        Self::require_active(&env);
+        Self::record_op(&env, &op_id);
+
+        if amount < 0 {
+            panic!("Amount must be >= 0");
+        }
+
+        let vault = Self::vault(&env);
+        let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
+        let token_client = token::Client::new(&env, &usdc_token);
+        token_client.approve(
+            &env.current_contract_address(),
+            &vault,
+            &amount,
+            &expiration_ledger,
+        );
+        env.storage()
+            .instance()
+            .set(&DataKey::ApprovedVault, &vault);
+
+        VaultAllowanceSetEvent {
+            vault,
+            amount,
+            expiration_ledger,
+        }
+        .publish(&env);
+    }
+
+    /// Create a recurring payment paid by this wallet (owner only)
+    pub fn create_recurring_payment(
+        env: Env,
+        caller: Address,
+        recipient: Address,
+        amount: i128,
+        interval_ledgers: u32,
+        max_cycles: u32,
+        op_id: BytesN<32>,
+    ) -> u64 {
+        Self::require_owner(&env, &caller);
+        caller.require_auth();
+        Self::require_active(&env);
+        Self::record_op(&env, &op_id);
+
+        let vault = Self::vault(&env);
+        CheeseVaultClient::new(&env, &vault).create_schedule(
+            &env.current_contract_address(),
+            &recipient,
+            &amount,
+            &interval_ledgers,
+            &max_cycles,
+        )
+    }
+
+    /// Pause or resume a recurring payment paid by this wallet (backend or owner)
+    pub fn set_recurring_payment_paused(
+        env: Env,
+        caller: Address,
+        schedule_id: u64,
+        paused: bool,
+        op_id: BytesN<32>,
+    ) {
+        Self::require_backend_or_owner(&env, &caller);
+        caller.require_auth();
+        Self::record_op(&env, &op_id);
+
+        let vault = Self::vault(&env);
+        let vault_client = CheeseVaultClient::new(&env, &vault);
+        if paused {
+            vault_client.pause_schedule(&schedule_id);
+        } else {
+            vault_client.resume_schedule(&schedule_id);
+        }
+    }
+
+    /// Cancel a recurring payment paid by this wallet (backend or owner)
+    pub fn cancel_recurring_payment(
+        env: Env,
+        caller: Address,
+        schedule_id: u64,
+        op_id: BytesN<32>,
+    ) {
+        Self::require_backend_or_owner(&env, &caller);
+        caller.require_auth();
+        Self::record_op(&env, &op_id);
+
+        let vault = Self::vault(&env);
+        CheeseVaultClient::new(&env, &vault).cancel_schedule(&schedule_id);
+    }
+
+    // View functions
+    pub fn get_backend(env: Env) -> Address {
+        Self::backend(&env)
+    }
+
+    pub fn get_owner(env: Env) -> Option<Address> {
+        env.storage().instance().get(&DataKey::Owner)
+    }
+
+    pub fn get_vault(env: Env) -> Address {
+        Self::vault(&env)
+    }
+
+    pub fn get_factory(env: Env) -> Option<Address> {
+        env.storage().instance().get(&DataKey::Factory)
+    }
+
+    pub fn get_version(env: Env) -> u32 {
+        env.storage().instance().get(&DataKey::Version).unwrap()
+    }
+
+    pub fn get_status(env: Env) -> WalletStatus {
+        env.storage()
+            .instance()
+            .get(&DataKey::Status)
+            .unwrap_or(WalletStatus::Active)
+    }
+
+    pub fn is_upgrade_opted_out(env: Env) -> bool {
+        env.storage()
+            .instance()
+            .get(&DataKey::UpgradeOptOut)
+            .unwrap_or(false)
+    }
+
+    pub fn is_allowlist_enabled(env: Env) -> bool {
+        env.storage()
+            .instance()
+            .get(&DataKey::AllowlistEnabled)
+            .unwrap_or(false)
+    }
+
+    /// Whether the backend may currently withdraw to `recipient`
+    pub fn is_recipient_allowed(env: Env, recipient: Address) -> bool {
+        if !Self::is_allowlist_enabled(env.clone()) {
+            return true;
+        }
+
+        let active_from_ledger: Option<u32> = env
+            .storage()
+            .instance()
+            .get(&DataKey::AllowedRecipient(recipient));
+        match active_from_ledger {
+            Some(ledger) => env.ledger().sequence() >= ledger,
+            None => false,
+        }
+    }
+
+    pub fn get_guardians(env: Env) -> Vec<Address> {
+        env.storage()
+            .instance()
+            .get(&DataKey::Guardians)
+            .unwrap_or(Vec::new(&env))
+    }
+
+    /// Recovery proposal for `new_owner`, if any guardian backs it
+    pub fn get_recovery(env: Env, new_owner: Address) -> Option<RecoveryProposal> {
+        Self::get_recovery_proposals(env)
+            .iter()
+            .find(|p| p.new_owner == new_owner)
+    }
+
+    pub fn get_recovery_proposals(env: Env) -> Vec<RecoveryProposal> {
+        env.storage()
+            .instance()
+            .get(&DataKey::Recovery)
+            .unwrap_or(Vec::new(&env))
+    }
+
+    pub fn get_emergency_delay(env: Env) -> u32 {
+        env.storage()
+            .instance()
+            .get(&DataKey::EmergencyDelay)
+            .unwrap_or(DEFAULT_EMERGENCY_DELAY_LEDGERS)
+    }
+
+    /// Ledger from which a requested emergency exit can be executed, if any
+    pub fn get_pending_emergency_exit(env: Env) -> Option<u32> {
+        env.storage().instance().get(&DataKey::EmergencyExit)
+    }
+
+    pub fn is_payment_committed(env: Env, payment_id: BytesN<32>) -> bool {
+        Self::live_commitments(&env)
+            .iter()
+            .any(|c| c.payment_id == payment_id)
+    }
+
+    /// Committed payments that have not been settled or lapsed
+    pub fn get_committed_payments(env: Env) -> Vec<CommittedPayment> {
+        Self::live_commitments(&env)
+    }
+
+    pub fn get_signer(env: Env, signer: SignerKey) -> Option<SignerRole> {
+        env.storage().instance().get(&DataKey::Signer(signer))
+    }
+
+    /// All granted session keys, including expired ones not yet revoked.
+    pub fn get_session_keys(env: Env) -> Vec<SessionKey> {
+        let mut sessions = Vec::new(&env);
+        for key in Self::get_session_keys_list(&env).iter() {
+            if let Some(session) = env.storage().instance().get(&DataKey::Session(key)) {
+                sessions.push_back(session);
+            }
+        }
+        sessions
+    }
+
+    pub fn get_session_key(env: Env, key: SignerKey) -> Option<SessionKey> {
+        env.storage().instance().get(&DataKey::Session(key))
+    }
+
+    /// Whether a backend operation with this id already ran (within the
+    /// retention window of about 30 days)
+    pub fn was_executed(env: Env, op_id: BytesN<32>) -> bool {
+        env.storage().temporary().has(&DataKey::ExecutedOp(op_id))
+    }
+
+    /// Supported assets, settlement asset first
+    pub fn get_assets(env: Env) -> Vec<Address> {
+        env.storage().instance().get(&DataKey::Assets).unwrap()
+    }
+
+    pub fn is_asset_supported(env: Env, asset: Address) -> bool {
+        Self::get_assets(env).contains(&asset)
+    }
+
+    pub fn get_owner_passkey(env: Env) -> Option<BytesN<65>> {
+        env.storage().instance().get(&DataKey::OwnerPasskey)
+    }
+
+    pub fn get_payout_address(env: Env) -> Option<Address> {
+        env.storage().instance().get(&DataKey::PayoutAddress)
+    }
+
+    pub fn get_backend_mode(env: Env) -> BackendMode {
+        env.storage()
+            .instance()
+            .get(&DataKey::BackendMode)
+            .unwrap_or(BackendMode::Full)
+    }
+
+    pub fn get_spending_limits(env: Env) -> Option<SpendingLimits> {
+        env.storage().instance().get(&DataKey::SpendingLimits)
+    }
+
+    /// Amount the backend can still withdraw in the current window, or `None`
+    /// when no limits are configured.
+    pub fn get_remaining_allowance(env: Env) -> Option<i128> {
+        let limits: SpendingLimits = env.storage().instance().get(&DataKey::SpendingLimits)?;
+        let spent = Self::window_total(&Self::current_spending_window(&env, &limits));
+        Some((limits.daily_cap - spent).max(0))
+    }
+
+    /// Backend spends made within the last `window_ledgers` ledgers.
+    fn current_spending_window(env: &Env, limits: &SpendingLimits) -> Vec<SpendRecord> {
+        let current_ledger = env.ledger().sequence();
+        let spends: Vec<SpendRecord> = env
+            .storage()
+            .instance()
+            .get(&DataKey::SpendingWindow)
+            .unwrap_or_else(|| Vec::new(env));
+
+        let mut window = Vec::new(env);
+        for spend in spends.iter() {
+            if current_ledger < spend.ledger.saturating_add(limits.window_ledgers) {
+                window.push_back(spend);
+            }
+        }
+        window
+    }
+
+    fn window_total(window: &Vec<SpendRecord>) -> i128 {
+        window.iter().map(|spend| spend.amount).sum()
+    }
+
+    fn consume_spending_limit(env: &Env, amount: i128) {
+        let limits: SpendingLimits = match env.storage().instance().get(&DataKey::SpendingLimits) {
+            Some(l) => l,
+            None => return,
+        };
+
+        if amount > limits.per_tx_max {
+            panic!("Exceeds per-transaction limit");
+        }
+
+        let mut window = Self::current_spending_window(env, &limits);
+        let spent = Self::window_total(&window)
+            .checked_add(amount)
+            .expect("Amount overflow");
+        if spent > limits.daily_cap {
+            panic!("Exceeds daily limit");
+        }
+        if window.len() >= MAX_WINDOW_SPENDS {
+            panic!("Too many spends in window");
+        }
+        window.push_back(SpendRecord {
+            ledger: env.ledger().sequence(),
+            amount,
+        });
+        env.storage()
+            .instance()
+            .set(&DataKey::SpendingWindow, &window);
+
+        if spent >= limits.daily_cap {
+            SpendingLimitReachedEvent {
+                spent,
+                daily_cap: limits.daily_cap,
+                window_start: window.get(0).unwrap().ledger,
+            }
+            .publish(env);
+        }
+    }
+
+    /// Stored committed payments without the lapsed ones
+    fn live_commitments(env: &Env) -> Vec<CommittedPayment> {
+        let commitments: Vec<CommittedPayment> = env
+            .storage()
+            .instance()
+            .get(&DataKey::CommittedPayments)
+            .unwrap_or(Vec::new(env));
+        let ledger = env.ledger().sequence();
+
+        let mut live = Vec::new(env);
+        for commitment in commitments.iter() {
+            if commitment.expiry_ledger >= ledger {
+                live.push_back(commitment);
+            }
+        }
+        live
+    }
+
+    /// Current backend: the factory's unless the owner pinned one, otherwise
+    /// the one set at construction.
+    fn backend(env: &Env) -> Address {
+        let factory: Option<Address> = env.storage().instance().get(&DataKey::Factory);
+        let pinned = env.storage().instance().has(&DataKey::BackendPinned);
+        match factory {
+            Some(factory) if !pinned => WalletFactoryClient::new(env, &factory).get_backend(),
+            _ => env.storage().instance().get(&DataKey::Backend).unwrap(),
+        }
+    }
+
+    /// Whether `caller` may act as backend: any backend operator of the
+    /// factory unless the owner pinned a backend, otherwise the stored one.
+    fn is_backend(env: &Env, caller: &Address) -> bool {
+        let factory: Option<Address> = env.storage().instance().get(&DataKey::Factory);
+        let pinned = env.storage().instance().has(&DataKey::BackendPinned);
+        match factory {
+            Some(factory) if !pinned => WalletFactoryClient::new(env, &factory).is_backend(caller),
+            _ => {
+                let backend: Address = env.storage().instance().get(&DataKey::Backend).unwrap();
+                *caller == backend
+            }
+        }
+    }
+
+    /// Current vault: the factory's if this wallet was deployed by one.
+    fn vault(env: &Env) -> Address {
+        let factory: Option<Address> = env.storage().instance().get(&DataKey::Factory);
+        match factory {
+            Some(factory) => WalletFactoryClient::new(env, &factory).get_vault(),
+            None => env.storage().instance().get(&DataKey::Vault).unwrap(),
+        }
+    }
+
+    /// Reject a replayed operation id, otherwise remember it for
+    /// `EXECUTED_OP_TTL_LEDGERS`.
+    fn record_op(env: &Env, op_id: &BytesN<32>) {
+        let key = DataKey::ExecutedOp(op_id.clone());
+        if env.storage().temporary().has(&key) {
+            panic!("Operation already executed");
+        }
+        env.storage()
+            .temporary()
+            .set(&key, &env.ledger().sequence());
+        env.storage().temporary().extend_ttl(
+            &key,
+            EXECUTED_OP_TTL_LEDGERS,
+            EXECUTED_OP_TTL_LEDGERS,
+        );
+    }
+
+    /// Transfer the full balance of every supported asset to `to`, returning
+    /// what was moved.
+    fn sweep(env: &Env, to: &Address) -> Vec<AssetBalance> {
+        let wallet = env.current_contract_address();
+        let mut swept = Vec::new(env);
+        for AssetBalance { asset, balance } in Self::get_balances(env.clone()).iter() {
+            if balance <= 0 {
+                continue;
+            }
+            // A failing token must not block exits for the other assets.
+            let token_client = token::Client::new(env, &asset);
+            if token_client.try_transfer(&wallet, to, &balance).is_ok() {
+                swept.push_back(AssetBalance { asset, balance });
+            }
+        }
+        swept
+    }
+
+    /// Zero the allowance granted by `approve_vault`, if any. Uses the stored
+    /// spender so it works while the factory is calling in.
+    fn revoke_vault_allowance(env: &Env) {
+        let vault: Option<Address> = env.storage().instance().get(&DataKey::ApprovedVault);
+        if let Some(vault) = vault {
+            let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
+            token::Client::new(env, &usdc_token).approve(
+                &env.current_contract_address(),
+                &vault,
+                &0,
+                &env.ledger().sequence(),
+            );
+            env.storage().instance().remove(&DataKey::ApprovedVault);
+        }
+    }
+
+    fn get_session_keys_list(env: &Env) -> Vec<SignerKey> {
+        env.storage()
+            .instance()
+            .get(&DataKey::SessionKeys)
+            .unwrap_or(Vec::new(env))
+    }
+
+    /// Store a new owner and drop the passkey signer of the previous one.
+    fn replace_owner(env: &Env, new_owner: &Address) -> Option<Address> {
+        let old_owner: Option<Address> = env.storage().instance().get(&DataKey::Owner);
+        env.storage().instance().set(&DataKey::Owner, new_owner);
+
+        let old_passkey: Option<BytesN<65>> = env.storage().instance().get(&DataKey::OwnerPasskey);
+        if let Some(public_key) = old_passkey {
+            env.storage()
+                .instance()
+                .remove(&DataKey::Signer(SignerKey::Secp256r1(public_key)));
+            env.storage().instance().remove(&DataKey::OwnerPasskey);
+        }
+        env.storage().instance().remove(&DataKey::PayoutAddress);
+
+        old_owner
+    }
+
+    /// Exit destination for `owner`: the owner itself, or the payout address
+    /// when the wallet owns itself through a passkey.
+    fn payout_address(env: &Env, owner: &Address) -> Address {
+        if *owner != env.current_contract_address() {
+            return owner.clone();
+        }
+        env.storage()
+            .instance()
+            .get(&DataKey::PayoutAddress)
+            .unwrap_or_else(|| panic!("Payout address not set"))
+    }
+
+    fn require_factory(env: &Env) {
+        let factory: Address = env
+            .storage()
+            .instance()
+            .get(&DataKey::Factory)
+            .expect("Not a factory wallet");
+        factory.require_auth();
+    }
+
+    fn require_active(env: &Env) {
+        match Self::get_status(env.clone()) {
+            WalletStatus::Active => {}
+            WalletStatus::Frozen => panic!("Wallet is frozen"),
+            WalletStatus::Closed => panic!("Wallet is closed"),
+        }
+    }
+
+    fn require_owner(env: &Env, caller: &Address) {
+        let owner: Address = env
+            .storage()
+            .instance()
+            .get(&DataKey::Owner)
+            .expect("Owner not set");
+
+        if *caller != owner {
+            panic!("Only owner");
+        }
+    }
+
+    fn require_backend_or_owner(env: &Env, caller: &Address) {
+        let owner_opt: Option<Address> = env.storage().instance().get(&DataKey::Owner);
+
+        let is_backend = Self::is_backend(env, caller);
+        let is_owner = owner_opt.is_some_and(|owner| *caller == owner);
+
+        if !is_backend && !is_owner {
+            panic!("Not authorized");
+        }
+        if !is_owner {
+            Self::require_backend_mode(env, BackendMode::Full);
+        }
+    }
+
+    /// Panic while the owner has an emergency exit pending
+    fn require_no_emergency_exit(env: &Env) {
+        if env.storage().instance().has(&DataKey::EmergencyExit) {
+            panic!("Emergency exit pending");
+        }
+    }
+
+    /// Panic unless the backend's current mode is at least as permissive as `required`.
+    fn require_backend_mode(env: &Env, required: BackendMode) {
+        let allowed = match Self::get_backend_mode(env.clone()) {
+            BackendMode::Full => true,
+            BackendMode::Restricted => required != BackendMode::Full,
+            BackendMode::Revoked => false,
+        };
+        if !allowed {
+            panic!("Backend access restricted");
+        }
     }
 }
 
-mod test;
\ No newline at end of file
+mod test;
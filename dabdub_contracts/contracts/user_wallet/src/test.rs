@@ -1,7 +1,24 @@
 #![cfg(test)]
-use crate::{UserWallet, UserWalletClient};
-use soroban_sdk::{testutils::Address as _, token, Address, Env};
-use vault::Vault;
+extern crate std;
+
+use crate::{
+    AccountError, AccountSignature, AssetBalance, BackendMode, PaymentReceipt, SessionKey,
+    SignatureProof, SignerKey, SignerRole, SpendingLimits, UserWallet, UserWalletClient,
+    WalletStatus, WebAuthnAssertion,
+};
+use access_control::{BACKEND_ROLE, COMPLIANCE_ROLE};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use ed25519_dalek::{Signer as _, SigningKey};
+use soroban_sdk::{
+    auth::{Context, ContractContext},
+    contract, contractimpl,
+    testutils::{Address as _, Ledger, MockAuth, MockAuthInvoke},
+    token, vec,
+    xdr::{self, Limits, WriteXdr},
+    Address, Bytes, BytesN, Env, IntoVal, Symbol, TryFromVal, Val, Vec,
+};
+use vault::{Vault, VaultClient};
+use wallet_factory::{DataKey as FactoryKey, WalletFactory, WalletFactoryClient};
 
 #[test]
 fn test_initialize() {
@@ -11,7 +28,18 @@ fn test_initialize() {
     let vault = Address::generate(&env);
     let usdc = Address::generate(&env);
 
-    let contract_id = env.register(UserWallet, (&backend, &vault, &usdc, &None::<Address>));
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
     let client = UserWalletClient::new(&env, &contract_id);
 
     assert_eq!(client.get_backend(), backend);
@@ -28,7 +56,18 @@ fn test_with_owner() {
     let usdc = Address::generate(&env);
     let owner = Address::generate(&env);
 
-    let contract_id = env.register(UserWallet, (&backend, &vault, &usdc, &Some(owner.clone())));
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
     let client = UserWalletClient::new(&env, &contract_id);
     assert_eq!(client.get_owner(), Some(owner));
 }
@@ -44,7 +83,18 @@ fn test_get_balance() {
     let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
     let usdc = asset_contract.address();
 
-    let contract_id = env.register(UserWallet, (&backend, &vault, &usdc, &None::<Address>));
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
     let client = UserWalletClient::new(&env, &contract_id);
 
     let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
@@ -65,13 +115,29 @@ fn test_withdraw_by_backend() {
     let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
     let usdc = asset_contract.address();
 
-    let contract_id = env.register(UserWallet, (&backend, &vault, &usdc, &None::<Address>));
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
     let client = UserWalletClient::new(&env, &contract_id);
 
     let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
     token_admin_client.mint(&contract_id, &1000_0000000);
 
-    client.withdraw(&backend, &500_0000000, &recipient);
+    client.withdraw(
+        &backend,
+        &500_0000000,
+        &recipient,
+        &BytesN::from_array(&env, &[2; 32]),
+    );
 
     assert_eq!(client.get_balance(), 500_0000000);
 
@@ -92,13 +158,29 @@ fn test_withdraw_by_owner() {
     let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
     let usdc = asset_contract.address();
 
-    let contract_id = env.register(UserWallet, (&backend, &vault, &usdc, &Some(owner.clone())));
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
     let client = UserWalletClient::new(&env, &contract_id);
 
     let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
     token_admin_client.mint(&contract_id, &1000_0000000);
 
-    client.withdraw(&owner, &300_0000000, &recipient);
+    client.withdraw(
+        &owner,
+        &300_0000000,
+        &recipient,
+        &BytesN::from_array(&env, &[3; 32]),
+    );
 
     assert_eq!(client.get_balance(), 700_0000000);
 }
@@ -115,10 +197,26 @@ fn test_withdraw_unauthorized() {
     let recipient = Address::generate(&env);
     let usdc = Address::generate(&env);
 
-    let contract_id = env.register(UserWallet, (&backend, &vault, &usdc, &None::<Address>));
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
     let client = UserWalletClient::new(&env, &contract_id);
 
-    client.withdraw(&unauthorized, &100_0000000, &recipient);
+    client.withdraw(
+        &unauthorized,
+        &100_0000000,
+        &recipient,
+        &BytesN::from_array(&env, &[4; 32]),
+    );
 }
 
 #[test]
@@ -134,13 +232,29 @@ fn test_withdraw_insufficient_balance() {
     let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
     let usdc = asset_contract.address();
 
-    let contract_id = env.register(UserWallet, (&backend, &vault, &usdc, &None::<Address>));
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
     let client = UserWalletClient::new(&env, &contract_id);
 
     let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
     token_admin_client.mint(&contract_id, &50_0000000);
 
-    client.withdraw(&backend, &100_0000000, &recipient);
+    client.withdraw(
+        &backend,
+        &100_0000000,
+        &recipient,
+        &BytesN::from_array(&env, &[5; 32]),
+    );
 }
 
 #[test]
@@ -153,10 +267,21 @@ fn test_set_owner() {
     let usdc = Address::generate(&env);
     let new_owner = Address::generate(&env);
 
-    let contract_id = env.register(UserWallet, (&backend, &vault, &usdc, &None::<Address>));
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
     let client = UserWalletClient::new(&env, &contract_id);
 
-    client.set_owner(&backend, &new_owner);
+    client.set_owner(&backend, &new_owner, &BytesN::from_array(&env, &[6; 32]));
 
     assert_eq!(client.get_owner(), Some(new_owner));
 }
@@ -173,10 +298,25 @@ fn test_set_owner_not_backend() {
     let not_backend = Address::generate(&env);
     let new_owner = Address::generate(&env);
 
-    let contract_id = env.register(UserWallet, (&backend, &vault, &usdc, &None::<Address>));
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
     let client = UserWalletClient::new(&env, &contract_id);
 
-    client.set_owner(&not_backend, &new_owner);
+    client.set_owner(
+        &not_backend,
+        &new_owner,
+        &BytesN::from_array(&env, &[7; 32]),
+    );
 }
 
 #[test]
@@ -191,15 +331,31 @@ fn test_emergency_withdraw() {
     let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
     let usdc = asset_contract.address();
 
-    let contract_id = env.register(UserWallet, (&backend, &vault, &usdc, &Some(owner.clone())));
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
     let client = UserWalletClient::new(&env, &contract_id);
 
     let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
     token_admin_client.mint(&contract_id, &1000_0000000);
 
+    let executable_ledger = client.request_emergency_withdraw(&owner);
+    assert_eq!(client.get_pending_emergency_exit(), Some(executable_ledger));
+
+    env.ledger().set_sequence_number(executable_ledger);
     client.emergency_withdraw(&owner);
 
     assert_eq!(client.get_balance(), 0);
+    assert_eq!(client.get_pending_emergency_exit(), None);
 
     let token_client = token::Client::new(&env, &usdc);
     assert_eq!(token_client.balance(&owner), 1000_0000000);
@@ -216,7 +372,18 @@ fn test_emergency_withdraw_no_owner() {
     let usdc = Address::generate(&env);
     let someone = Address::generate(&env);
 
-    let contract_id = env.register(UserWallet, (&backend, &vault, &usdc, &None::<Address>));
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
     let client = UserWalletClient::new(&env, &contract_id);
 
     client.emergency_withdraw(&someone);
@@ -234,7 +401,18 @@ fn test_emergency_withdraw_not_owner() {
     let not_owner = Address::generate(&env);
     let usdc = Address::generate(&env);
 
-    let contract_id = env.register(UserWallet, (&backend, &vault, &usdc, &Some(owner.clone())));
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
     let client = UserWalletClient::new(&env, &contract_id);
 
     client.emergency_withdraw(&not_owner);
@@ -254,21 +432,87 @@ fn test_transfer_to_vault_by_backend() {
 
     let vault_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
 
-    let wallet_id = env.register(UserWallet, (&backend, &vault_id, &usdc, &None::<Address>));
+    let wallet_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
     let wallet = UserWalletClient::new(&env, &wallet_id);
 
     // Mint tokens to wallet
     let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
     token_admin_client.mint(&wallet_id, &100_000_000);
 
-    // Transfer payment + fee to vault
-    let total_amount = wallet.transfer_to_vault(&backend, &50_000_000);
-    assert_eq!(total_amount, 50_500_000);
+    // Pay payment + fee into the vault and book it in one call
+    let payment_id = BytesN::from_array(&env, &[1; 32]);
+    let recipient = Address::generate(&env);
+    let receipt =
+        wallet.transfer_to_vault(&backend, &50_000_000, &payment_id, &recipient, &500_000);
+    assert_eq!(
+        receipt,
+        PaymentReceipt {
+            payment_id: payment_id.clone(),
+            payer: wallet_id.clone(),
+            recipient: recipient.clone(),
+            payment_amount: 50_000_000,
+            fee_amount: 500_000,
+            total_amount: 50_500_000,
+            expiry_ledger: receipt.expiry_ledger,
+        }
+    );
 
     // Verify balances
     let token_client = token::Client::new(&env, &usdc);
     assert_eq!(token_client.balance(&vault_id), 50_500_000);
     assert_eq!(token_client.balance(&wallet_id), 49_500_000);
+
+    // The vault booked the payment for the recipient
+    let vault = VaultClient::new(&env, &vault_id);
+    let claim = vault.get_pending_claim(&payment_id).unwrap();
+    assert_eq!(claim.recipient, recipient);
+    assert_eq!(claim.payment_amount, 50_000_000);
+    assert!(vault.verify_vault_accounting());
+}
+
+#[test]
+#[should_panic(expected = "Operation already executed")]
+fn test_transfer_to_vault_duplicate_payment_id() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let admin = Address::generate(&env);
+    let usdc = env
+        .register_stellar_asset_contract_v2(Address::generate(&env))
+        .address();
+
+    let vault_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
+    let wallet_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let wallet = UserWalletClient::new(&env, &wallet_id);
+    token::StellarAssetClient::new(&env, &usdc).mint(&wallet_id, &100_000_000);
+
+    let payment_id = BytesN::from_array(&env, &[1; 32]);
+    let recipient = Address::generate(&env);
+    wallet.transfer_to_vault(&backend, &10_000_000, &payment_id, &recipient, &500_000);
+    wallet.transfer_to_vault(&backend, &10_000_000, &payment_id, &recipient, &500_000);
 }
 
 #[test]
@@ -285,14 +529,31 @@ fn test_transfer_to_vault_insufficient_balance_including_fee() {
     let usdc = asset_contract.address();
 
     let vault_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
-    let wallet_id = env.register(UserWallet, (&backend, &vault_id, &usdc, &None::<Address>));
+    let wallet_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
     let wallet = UserWalletClient::new(&env, &wallet_id);
 
     // Only mint the payment amount (not enough for payment + fee)
     let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
     token_admin_client.mint(&wallet_id, &50_000_000);
 
-    wallet.transfer_to_vault(&backend, &50_000_000);
+    wallet.transfer_to_vault(
+        &backend,
+        &50_000_000,
+        &BytesN::from_array(&env, &[1; 32]),
+        &Address::generate(&env),
+        &500_000,
+    );
 }
 
 #[test]
@@ -310,8 +571,2848 @@ fn test_transfer_to_vault_unauthorized() {
     let usdc = asset_contract.address();
 
     let vault_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
-    let wallet_id = env.register(UserWallet, (&backend, &vault_id, &usdc, &None::<Address>));
+    let wallet_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
     let wallet = UserWalletClient::new(&env, &wallet_id);
 
-    wallet.transfer_to_vault(&unauthorized, &10_000_000);
+    wallet.transfer_to_vault(
+        &unauthorized,
+        &10_000_000,
+        &BytesN::from_array(&env, &[1; 32]),
+        &Address::generate(&env),
+        &500_000,
+    );
+}
+
+#[test]
+fn test_rescue_foreign_token() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let owner = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+    let usdc = env
+        .register_stellar_asset_contract_v2(token_admin.clone())
+        .address();
+    let other = env
+        .register_stellar_asset_contract_v2(token_admin.clone())
+        .address();
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(&env, &contract_id);
+
+    token::StellarAssetClient::new(&env, &other).mint(&contract_id, &3_000_000);
+
+    client.rescue_token(
+        &owner,
+        &other,
+        &owner,
+        &3_000_000,
+        &BytesN::from_array(&env, &[8; 32]),
+    );
+
+    assert_eq!(token::Client::new(&env, &other).balance(&owner), 3_000_000);
+}
+
+#[test]
+fn test_backend_rescues_only_to_owner() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let owner = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+    let usdc = env
+        .register_stellar_asset_contract_v2(token_admin.clone())
+        .address();
+    let other = env
+        .register_stellar_asset_contract_v2(token_admin.clone())
+        .address();
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(&env, &contract_id);
+
+    token::StellarAssetClient::new(&env, &other).mint(&contract_id, &3_000_000);
+
+    let result = client.try_rescue_token(
+        &backend,
+        &other,
+        &backend,
+        &3_000_000,
+        &BytesN::from_array(&env, &[8; 32]),
+    );
+    assert!(result.is_err());
+
+    client.rescue_token(
+        &backend,
+        &other,
+        &owner,
+        &3_000_000,
+        &BytesN::from_array(&env, &[9; 32]),
+    );
+    assert_eq!(token::Client::new(&env, &other).balance(&owner), 3_000_000);
+}
+
+#[test]
+#[should_panic(expected = "Cannot rescue settlement asset")]
+fn test_rescue_settlement_asset_rejected() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let owner = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+    let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let usdc = asset_contract.address();
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(&env, &contract_id);
+
+    token::StellarAssetClient::new(&env, &usdc).mint(&contract_id, &1_000_000);
+
+    client.rescue_token(
+        &owner,
+        &usdc,
+        &recipient,
+        &1_000_000,
+        &BytesN::from_array(&env, &[9; 32]),
+    );
+}
+
+#[test]
+fn test_backend_withdraw_within_spending_limits() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let owner = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+    let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let usdc = asset_contract.address();
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(&env, &contract_id);
+
+    let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
+    token_admin_client.mint(&contract_id, &1000_0000000);
+
+    let limits = SpendingLimits {
+        per_tx_max: 100_0000000,
+        daily_cap: 150_0000000,
+        window_ledgers: 17_280,
+    };
+    client.set_spending_limits(&owner, &limits, &BytesN::from_array(&env, &[60; 32]));
+    assert_eq!(client.get_spending_limits(), Some(limits));
+    assert_eq!(client.get_remaining_allowance(), Some(150_0000000));
+
+    client.withdraw(
+        &backend,
+        &100_0000000,
+        &recipient,
+        &BytesN::from_array(&env, &[10; 32]),
+    );
+    client.withdraw(
+        &backend,
+        &50_0000000,
+        &recipient,
+        &BytesN::from_array(&env, &[11; 32]),
+    );
+    assert_eq!(client.get_remaining_allowance(), Some(0));
+
+    // Owner withdrawals are not limited
+    client.withdraw(
+        &owner,
+        &500_0000000,
+        &recipient,
+        &BytesN::from_array(&env, &[12; 32]),
+    );
+
+    // A new window restores the allowance
+    env.ledger().set_sequence_number(17_280);
+    assert_eq!(client.get_remaining_allowance(), Some(150_0000000));
+    client.withdraw(
+        &backend,
+        &100_0000000,
+        &recipient,
+        &BytesN::from_array(&env, &[13; 32]),
+    );
+
+    let token_client = token::Client::new(&env, &usdc);
+    assert_eq!(token_client.balance(&recipient), 750_0000000);
+}
+
+#[test]
+fn test_spending_window_rolls_per_spend() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let owner = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let usdc = env
+        .register_stellar_asset_contract_v2(Address::generate(&env))
+        .address();
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &Address::generate(&env),
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(&env, &contract_id);
+    token::StellarAssetClient::new(&env, &usdc).mint(&contract_id, &1000_0000000);
+
+    client.set_spending_limits(
+        &owner,
+        &SpendingLimits {
+            per_tx_max: 100_0000000,
+            daily_cap: 150_0000000,
+            window_ledgers: 17_280,
+        },
+        &BytesN::from_array(&env, &[61; 32]),
+    );
+
+    client.withdraw(
+        &backend,
+        &100_0000000,
+        &recipient,
+        &BytesN::from_array(&env, &[10; 32]),
+    );
+    env.ledger().set_sequence_number(10_000);
+    client.withdraw(
+        &backend,
+        &50_0000000,
+        &recipient,
+        &BytesN::from_array(&env, &[11; 32]),
+    );
+
+    // Only the first spend has left the window; the second still counts.
+    env.ledger().set_sequence_number(17_280);
+    assert_eq!(client.get_remaining_allowance(), Some(100_0000000));
+    let result = client.try_withdraw(
+        &backend,
+        &100_0000001,
+        &recipient,
+        &BytesN::from_array(&env, &[12; 32]),
+    );
+    assert!(result.is_err());
+
+    env.ledger().set_sequence_number(27_280);
+    assert_eq!(client.get_remaining_allowance(), Some(150_0000000));
+}
+
+#[test]
+#[should_panic(expected = "Exceeds daily limit")]
+fn test_backend_withdraw_exceeds_daily_limit() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let owner = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+    let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let usdc = asset_contract.address();
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(&env, &contract_id);
+
+    let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
+    token_admin_client.mint(&contract_id, &1000_0000000);
+
+    let limits = SpendingLimits {
+        per_tx_max: 100_0000000,
+        daily_cap: 150_0000000,
+        window_ledgers: 17_280,
+    };
+    client.set_spending_limits(&owner, &limits, &BytesN::from_array(&env, &[62; 32]));
+
+    client.withdraw(
+        &backend,
+        &100_0000000,
+        &recipient,
+        &BytesN::from_array(&env, &[14; 32]),
+    );
+    client.withdraw(
+        &backend,
+        &100_0000000,
+        &recipient,
+        &BytesN::from_array(&env, &[15; 32]),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Exceeds per-transaction limit")]
+fn test_backend_withdraw_exceeds_per_tx_limit() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+    let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let usdc = asset_contract.address();
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(&env, &contract_id);
+
+    let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
+    token_admin_client.mint(&contract_id, &1000_0000000);
+
+    let limits = SpendingLimits {
+        per_tx_max: 100_0000000,
+        daily_cap: 500_0000000,
+        window_ledgers: 17_280,
+    };
+    client.set_spending_limits(&backend, &limits, &BytesN::from_array(&env, &[63; 32]));
+
+    client.withdraw(
+        &backend,
+        &200_0000000,
+        &recipient,
+        &BytesN::from_array(&env, &[16; 32]),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Only owner can raise limits")]
+fn test_backend_cannot_raise_spending_limits() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let owner = Address::generate(&env);
+    let usdc = Address::generate(&env);
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(&env, &contract_id);
+
+    client.set_spending_limits(
+        &owner,
+        &SpendingLimits {
+            per_tx_max: 100_0000000,
+            daily_cap: 150_0000000,
+            window_ledgers: 17_280,
+        },
+        &BytesN::from_array(&env, &[64; 32]),
+    );
+
+    client.set_spending_limits(
+        &backend,
+        &SpendingLimits {
+            per_tx_max: 100_0000000,
+            daily_cap: 300_0000000,
+            window_ledgers: 17_280,
+        },
+        &BytesN::from_array(&env, &[65; 32]),
+    );
+}
+
+#[test]
+fn test_backend_withdraw_to_allowlisted_recipient_after_delay() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let owner = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let other = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+    let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let usdc = asset_contract.address();
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(&env, &contract_id);
+
+    let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
+    token_admin_client.mint(&contract_id, &1000_0000000);
+
+    client.set_allowlist_enabled(&owner, &true);
+    client.add_allowed_recipient(&owner, &recipient);
+    assert!(!client.is_recipient_allowed(&recipient));
+
+    env.ledger().set_sequence_number(17_280);
+    assert!(client.is_recipient_allowed(&recipient));
+    assert!(!client.is_recipient_allowed(&other));
+
+    client.withdraw(
+        &backend,
+        &100_0000000,
+        &recipient,
+        &BytesN::from_array(&env, &[17; 32]),
+    );
+    // Owner withdrawals ignore the allowlist
+    client.withdraw(
+        &owner,
+        &100_0000000,
+        &other,
+        &BytesN::from_array(&env, &[18; 32]),
+    );
+
+    let token_client = token::Client::new(&env, &usdc);
+    assert_eq!(token_client.balance(&recipient), 100_0000000);
+    assert_eq!(token_client.balance(&other), 100_0000000);
+}
+
+#[test]
+#[should_panic(expected = "Recipient not allowlisted")]
+fn test_backend_withdraw_before_allowlist_delay() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let owner = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+    let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let usdc = asset_contract.address();
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(&env, &contract_id);
+
+    let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
+    token_admin_client.mint(&contract_id, &1000_0000000);
+
+    client.set_allowlist_enabled(&owner, &true);
+    client.add_allowed_recipient(&owner, &recipient);
+
+    client.withdraw(
+        &backend,
+        &100_0000000,
+        &recipient,
+        &BytesN::from_array(&env, &[19; 32]),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Only owner")]
+fn test_backend_cannot_manage_allowlist() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let owner = Address::generate(&env);
+    let usdc = Address::generate(&env);
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(&env, &contract_id);
+
+    client.set_allowlist_enabled(&owner, &true);
+    client.set_allowlist_enabled(&backend, &false);
+}
+
+#[test]
+fn test_owner_rotates_own_key() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let owner = Address::generate(&env);
+    let new_owner = Address::generate(&env);
+    let usdc = Address::generate(&env);
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(&env, &contract_id);
+
+    client.rotate_owner(&owner, &new_owner);
+
+    assert_eq!(client.get_owner(), Some(new_owner));
+}
+
+#[test]
+#[should_panic(expected = "Backend access restricted")]
+fn test_revoked_backend_cannot_withdraw() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let owner = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+    let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let usdc = asset_contract.address();
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(&env, &contract_id);
+
+    let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
+    token_admin_client.mint(&contract_id, &1000_0000000);
+
+    client.set_backend_mode(&owner, &BackendMode::Revoked);
+    assert_eq!(client.get_backend_mode(), BackendMode::Revoked);
+
+    client.withdraw(
+        &backend,
+        &100_0000000,
+        &recipient,
+        &BytesN::from_array(&env, &[20; 32]),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Backend access restricted")]
+fn test_restricted_backend_cannot_set_owner() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let owner = Address::generate(&env);
+    let attacker = Address::generate(&env);
+    let usdc = Address::generate(&env);
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(&env, &contract_id);
+
+    client.set_backend_mode(&owner, &BackendMode::Restricted);
+
+    client.set_owner(&backend, &attacker, &BytesN::from_array(&env, &[21; 32]));
+}
+
+#[test]
+fn test_restricted_backend_can_transfer_to_vault() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let admin = Address::generate(&env);
+    let owner = Address::generate(&env);
+
+    let token_admin = Address::generate(&env);
+    let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let usdc = asset_contract.address();
+
+    let vault_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
+    let wallet_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault_id,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let wallet = UserWalletClient::new(&env, &wallet_id);
+
+    let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
+    token_admin_client.mint(&wallet_id, &100_000_000);
+
+    wallet.set_backend_mode(&owner, &BackendMode::Restricted);
+
+    let receipt = wallet.transfer_to_vault(
+        &backend,
+        &50_000_000,
+        &BytesN::from_array(&env, &[1; 32]),
+        &Address::generate(&env),
+        &500_000,
+    );
+    assert_eq!(receipt.total_amount, 50_500_000);
+}
+
+#[test]
+fn test_restricted_backend_cannot_drain_through_vault() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let admin = Address::generate(&env);
+    let owner = Address::generate(&env);
+    let attacker = Address::generate(&env);
+    let merchant = Address::generate(&env);
+
+    let token_admin = Address::generate(&env);
+    let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let usdc = asset_contract.address();
+
+    let vault_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
+    let wallet_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault_id,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let wallet = UserWalletClient::new(&env, &wallet_id);
+    token::StellarAssetClient::new(&env, &usdc).mint(&wallet_id, &100_000_000);
+
+    wallet.set_spending_limits(
+        &owner,
+        &SpendingLimits {
+            per_tx_max: 1_000_000,
+            daily_cap: 1_000_000,
+            window_ledgers: 17_280,
+        },
+        &BytesN::from_array(&env, &[66; 32]),
+    );
+    wallet.add_allowed_recipient(&owner, &merchant);
+    wallet.set_allowlist_enabled(&owner, &true);
+    wallet.set_backend_mode(&owner, &BackendMode::Restricted);
+    env.ledger().set_sequence_number(17_280);
+
+    let result = wallet.try_transfer_to_vault(
+        &backend,
+        &99_000_000,
+        &BytesN::from_array(&env, &[1; 32]),
+        &attacker,
+        &500_000,
+    );
+    assert!(result.is_err());
+
+    // An allowlisted recipient is still held to the limits, fee included.
+    let result = wallet.try_transfer_to_vault(
+        &backend,
+        &99_000_000,
+        &BytesN::from_array(&env, &[2; 32]),
+        &merchant,
+        &500_000,
+    );
+    assert!(result.is_err());
+    let result = wallet.try_transfer_to_vault(
+        &backend,
+        &1_000_000,
+        &BytesN::from_array(&env, &[3; 32]),
+        &merchant,
+        &500_000,
+    );
+    assert!(result.is_err());
+
+    let receipt = wallet.transfer_to_vault(
+        &backend,
+        &500_000,
+        &BytesN::from_array(&env, &[4; 32]),
+        &merchant,
+        &500_000,
+    );
+    assert_eq!(receipt.total_amount, 1_000_000);
+    assert_eq!(wallet.get_balance(), 99_000_000);
+    assert_eq!(wallet.get_remaining_allowance(), Some(0));
+}
+
+#[test]
+fn test_owner_reauthorizes_backend() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let new_backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let owner = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+    let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let usdc = asset_contract.address();
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(&env, &contract_id);
+
+    let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
+    token_admin_client.mint(&contract_id, &1000_0000000);
+
+    client.set_backend_mode(&owner, &BackendMode::Revoked);
+    client.authorize_backend(&owner, &new_backend);
+
+    assert_eq!(client.get_backend(), new_backend);
+    assert_eq!(client.get_backend_mode(), BackendMode::Full);
+
+    client.withdraw(
+        &new_backend,
+        &100_0000000,
+        &recipient,
+        &BytesN::from_array(&env, &[22; 32]),
+    );
+    assert_eq!(client.get_balance(), 900_0000000);
+}
+
+fn setup_guarded_wallet(env: &Env) -> (UserWalletClient<'static>, Address, Vec<Address>) {
+    let backend = Address::generate(env);
+    let vault = Address::generate(env);
+    let owner = Address::generate(env);
+    let usdc = Address::generate(env);
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(env, &contract_id);
+
+    let guardians = vec![
+        env,
+        Address::generate(env),
+        Address::generate(env),
+        Address::generate(env),
+    ];
+    client.set_guardians(&owner, &guardians, &2);
+
+    (client, owner, guardians)
+}
+
+#[test]
+fn test_guardian_recovery_after_delay() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (client, _owner, guardians) = setup_guarded_wallet(&env);
+    let new_owner = Address::generate(&env);
+
+    client.support_recovery(&guardians.get(0).unwrap(), &new_owner);
+    assert_eq!(
+        client.get_recovery(&new_owner).unwrap().executable_ledger,
+        None
+    );
+
+    client.support_recovery(&guardians.get(2).unwrap(), &new_owner);
+    assert_eq!(
+        client.get_recovery(&new_owner).unwrap().executable_ledger,
+        Some(51_840)
+    );
+
+    env.ledger().set_sequence_number(51_840);
+    client.execute_recovery();
+
+    assert_eq!(client.get_owner(), Some(new_owner.clone()));
+    assert!(client.get_recovery(&new_owner).is_none());
+}
+
+#[test]
+fn test_competing_recovery_proposals() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (client, _owner, guardians) = setup_guarded_wallet(&env);
+    let rogue_pick = Address::generate(&env);
+    let new_owner = Address::generate(&env);
+
+    // A rogue guardian's proposal does not block the others.
+    client.support_recovery(&guardians.get(0).unwrap(), &rogue_pick);
+    client.support_recovery(&guardians.get(1).unwrap(), &new_owner);
+    assert_eq!(client.get_recovery_proposals().len(), 2);
+
+    client.support_recovery(&guardians.get(2).unwrap(), &new_owner);
+    assert_eq!(
+        client.get_recovery(&new_owner).unwrap().executable_ledger,
+        Some(51_840)
+    );
+    assert_eq!(
+        client.get_recovery(&rogue_pick).unwrap().executable_ledger,
+        None
+    );
+
+    env.ledger().set_sequence_number(51_840);
+    client.execute_recovery();
+    assert_eq!(client.get_owner(), Some(new_owner));
+    assert!(client.get_recovery_proposals().is_empty());
+}
+
+#[test]
+fn test_guardian_moves_support_to_another_owner() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (client, _owner, guardians) = setup_guarded_wallet(&env);
+    let first_pick = Address::generate(&env);
+    let second_pick = Address::generate(&env);
+
+    client.support_recovery(&guardians.get(0).unwrap(), &first_pick);
+    client.support_recovery(&guardians.get(1).unwrap(), &first_pick);
+    assert!(client
+        .get_recovery(&first_pick)
+        .unwrap()
+        .executable_ledger
+        .is_some());
+
+    // Moving an approval drops the first proposal below the threshold.
+    client.support_recovery(&guardians.get(1).unwrap(), &second_pick);
+    assert_eq!(
+        client.get_recovery(&first_pick).unwrap().executable_ledger,
+        None
+    );
+    assert_eq!(
+        client.get_recovery(&second_pick).unwrap().approvals.len(),
+        1
+    );
+
+    env.ledger().set_sequence_number(51_840);
+    let result = client.try_execute_recovery();
+    assert!(result.is_err());
+}
+
+#[test]
+#[should_panic(expected = "Recovery delay not elapsed")]
+fn test_guardian_recovery_before_delay() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (client, _owner, guardians) = setup_guarded_wallet(&env);
+    let new_owner = Address::generate(&env);
+
+    client.support_recovery(&guardians.get(0).unwrap(), &new_owner);
+    client.support_recovery(&guardians.get(1).unwrap(), &new_owner);
+
+    env.ledger().set_sequence_number(51_839);
+    client.execute_recovery();
+}
+
+#[test]
+#[should_panic(expected = "No recovery in progress")]
+fn test_owner_vetoes_recovery() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (client, owner, guardians) = setup_guarded_wallet(&env);
+    let new_owner = Address::generate(&env);
+
+    client.support_recovery(&guardians.get(0).unwrap(), &new_owner);
+    client.support_recovery(&guardians.get(1).unwrap(), &new_owner);
+    client.veto_recovery(&owner);
+
+    env.ledger().set_sequence_number(51_840);
+    client.execute_recovery();
+}
+
+#[test]
+#[should_panic(expected = "Only guardian")]
+fn test_non_guardian_cannot_support_recovery() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (client, _owner, _guardians) = setup_guarded_wallet(&env);
+    let outsider = Address::generate(&env);
+
+    client.support_recovery(&outsider, &outsider);
+}
+
+#[test]
+#[should_panic(expected = "Emergency delay not elapsed")]
+fn test_emergency_withdraw_before_delay() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let owner = Address::generate(&env);
+    let token_admin = Address::generate(&env);
+    let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let usdc = asset_contract.address();
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(&env, &contract_id);
+
+    let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
+    token_admin_client.mint(&contract_id, &1000_0000000);
+
+    client.set_emergency_delay(&owner, &100, &BytesN::from_array(&env, &[30; 32]));
+    assert_eq!(client.request_emergency_withdraw(&owner), 100);
+
+    env.ledger().set_sequence_number(99);
+    client.emergency_withdraw(&owner);
+}
+
+#[test]
+#[should_panic(expected = "No emergency exit requested")]
+fn test_emergency_withdraw_without_request() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let owner = Address::generate(&env);
+    let usdc = Address::generate(&env);
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(&env, &contract_id);
+
+    client.emergency_withdraw(&owner);
+}
+
+#[test]
+fn test_transfer_to_vault_during_pending_emergency_exit() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let admin = Address::generate(&env);
+    let owner = Address::generate(&env);
+
+    let token_admin = Address::generate(&env);
+    let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let usdc = asset_contract.address();
+
+    let vault_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
+    let wallet_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault_id,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let wallet = UserWalletClient::new(&env, &wallet_id);
+
+    let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
+    token_admin_client.mint(&wallet_id, &100_000_000);
+
+    let committed_id = BytesN::from_array(&env, &[1; 32]);
+    let merchant = Address::generate(&env);
+    wallet.commit_payment(&backend, &committed_id, &merchant, &50_000_000, &500_000);
+
+    let executable_ledger = wallet.request_emergency_withdraw(&owner);
+
+    // The committed terms can't be changed at settlement.
+    let result =
+        wallet.try_transfer_to_vault(&backend, &60_000_000, &committed_id, &merchant, &500_000);
+    assert!(result.is_err());
+    let result = wallet.try_transfer_to_vault(
+        &backend,
+        &50_000_000,
+        &committed_id,
+        &Address::generate(&env),
+        &500_000,
+    );
+    assert!(result.is_err());
+
+    wallet.transfer_to_vault(&backend, &50_000_000, &committed_id, &merchant, &500_000);
+    assert!(!wallet.is_payment_committed(&committed_id));
+
+    // Payments not committed before the request are held back.
+    let result = wallet.try_transfer_to_vault(
+        &backend,
+        &10_000_000,
+        &BytesN::from_array(&env, &[2; 32]),
+        &Address::generate(&env),
+        &500_000,
+    );
+    assert!(result.is_err());
+    let result = wallet.try_commit_payment(
+        &backend,
+        &BytesN::from_array(&env, &[3; 32]),
+        &merchant,
+        &10_000_000,
+        &500_000,
+    );
+    assert!(result.is_err());
+
+    env.ledger().set_sequence_number(executable_ledger);
+    wallet.emergency_withdraw(&owner);
+
+    let token_client = token::Client::new(&env, &usdc);
+    assert_eq!(token_client.balance(&vault_id), 50_500_000);
+    assert_eq!(token_client.balance(&owner), 49_500_000);
+}
+
+#[test]
+fn test_committed_payments_are_capped_and_lapse() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let owner = Address::generate(&env);
+    let merchant = Address::generate(&env);
+
+    let wallet_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &Address::generate(&env),
+            &Address::generate(&env),
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let wallet = UserWalletClient::new(&env, &wallet_id);
+
+    for i in 0..10u8 {
+        let payment_id = BytesN::from_array(&env, &[i; 32]);
+        wallet.commit_payment(&backend, &payment_id, &merchant, &1_000_000, &500_000);
+    }
+    let result = wallet.try_commit_payment(
+        &backend,
+        &BytesN::from_array(&env, &[10; 32]),
+        &merchant,
+        &1_000_000,
+        &500_000,
+    );
+    assert!(result.is_err());
+    let result = wallet.try_commit_payment(
+        &backend,
+        &BytesN::from_array(&env, &[0; 32]),
+        &merchant,
+        &1_000_000,
+        &500_000,
+    );
+    assert!(result.is_err());
+
+    // Lapsed commitments free their slots.
+    env.ledger().set_sequence_number(17_281);
+    assert_eq!(wallet.get_committed_payments().len(), 0);
+    wallet.commit_payment(
+        &backend,
+        &BytesN::from_array(&env, &[10; 32]),
+        &merchant,
+        &1_000_000,
+        &500_000,
+    );
+}
+
+#[test]
+fn test_backend_blocked_during_pending_emergency_exit() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let owner = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let usdc = env
+        .register_stellar_asset_contract_v2(Address::generate(&env))
+        .address();
+
+    let wallet_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &Address::generate(&env),
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let wallet = UserWalletClient::new(&env, &wallet_id);
+    token::StellarAssetClient::new(&env, &usdc).mint(&wallet_id, &100_000_000);
+
+    wallet.request_emergency_withdraw(&owner);
+
+    let result = wallet.try_withdraw(
+        &backend,
+        &10_000_000,
+        &recipient,
+        &BytesN::from_array(&env, &[1; 32]),
+    );
+    assert!(result.is_err());
+    let result = wallet.try_set_owner(&backend, &recipient, &BytesN::from_array(&env, &[2; 32]));
+    assert!(result.is_err());
+    let result = wallet.try_withdraw_asset(
+        &backend,
+        &usdc,
+        &10_000_000,
+        &recipient,
+        &BytesN::from_array(&env, &[5; 32]),
+    );
+    assert!(result.is_err());
+
+    // The owner can still move funds, and cancelling lifts the block.
+    wallet.withdraw(
+        &owner,
+        &10_000_000,
+        &recipient,
+        &BytesN::from_array(&env, &[3; 32]),
+    );
+    wallet.cancel_emergency_withdraw(&owner);
+    wallet.withdraw(
+        &backend,
+        &10_000_000,
+        &recipient,
+        &BytesN::from_array(&env, &[4; 32]),
+    );
+    wallet.withdraw_asset(
+        &backend,
+        &usdc,
+        &10_000_000,
+        &recipient,
+        &BytesN::from_array(&env, &[6; 32]),
+    );
+    assert_eq!(wallet.get_balance(), 70_000_000);
+}
+
+fn ed25519_signer(env: &Env, seed: u8) -> (SigningKey, SignerKey) {
+    let key = SigningKey::from_bytes(&[seed; 32]);
+    let public_key = BytesN::from_array(env, &key.verifying_key().to_bytes());
+    (key, SignerKey::Ed25519(public_key))
+}
+
+fn sign_payload(
+    env: &Env,
+    key: &SigningKey,
+    signer: &SignerKey,
+    payload: &BytesN<32>,
+) -> AccountSignature {
+    AccountSignature {
+        signer: signer.clone(),
+        proof: SignatureProof::Raw(BytesN::from_array(
+            env,
+            &key.sign(&payload.to_array()).to_bytes(),
+        )),
+    }
+}
+
+fn call_context(env: &Env, contract: &Address, fn_name: &str) -> Context {
+    Context::Contract(ContractContext {
+        contract: contract.clone(),
+        fn_name: Symbol::new(env, fn_name),
+        args: vec![env],
+    })
+}
+
+fn transfer_context(
+    env: &Env,
+    wallet: &Address,
+    payment_amount: i128,
+    recipient: &Address,
+    max_fee: i128,
+) -> Context {
+    Context::Contract(ContractContext {
+        contract: wallet.clone(),
+        fn_name: Symbol::new(env, "transfer_to_vault"),
+        args: (
+            wallet.clone(),
+            payment_amount,
+            BytesN::from_array(env, &[1; 32]),
+            recipient.clone(),
+            max_fee,
+        )
+            .into_val(env),
+    })
+}
+
+fn setup_account_wallet(env: &Env) -> (UserWalletClient<'static>, Address) {
+    env.mock_all_auths();
+
+    let backend = Address::generate(env);
+    let vault = Address::generate(env);
+    let usdc = Address::generate(env);
+    let owner = Address::generate(env);
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &Some(owner.clone()),
+            &Vec::<Address>::new(env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(env, &contract_id);
+    (client, owner)
+}
+
+#[test]
+fn test_owner_signer_authorizes_any_call() {
+    let env = Env::default();
+    let (client, owner) = setup_account_wallet(&env);
+    let (key, signer) = ed25519_signer(&env, 1);
+    client.add_signer(&owner, &signer, &SignerRole::Owner);
+    assert_eq!(client.get_signer(&signer), Some(SignerRole::Owner));
+
+    let payload = BytesN::from_array(&env, &[7; 32]);
+    let signatures = vec![&env, sign_payload(&env, &key, &signer, &payload)];
+    let contexts = vec![&env, call_context(&env, &client.address, "withdraw")];
+
+    assert_eq!(
+        env.try_invoke_contract_check_auth::<AccountError>(
+            &client.address,
+            &payload,
+            signatures.into_val(&env),
+            &contexts,
+        ),
+        Ok(())
+    );
+}
+
+#[test]
+fn test_backend_signer_limited_to_transfer_to_vault() {
+    let env = Env::default();
+    let (client, owner) = setup_account_wallet(&env);
+    let (key, signer) = ed25519_signer(&env, 2);
+    client.add_signer(&owner, &signer, &SignerRole::Backend);
+
+    let payload = BytesN::from_array(&env, &[9; 32]);
+    let signatures = vec![&env, sign_payload(&env, &key, &signer, &payload)];
+
+    let merchant = Address::generate(&env);
+    let allowed = vec![
+        &env,
+        transfer_context(&env, &client.address, 1_000_000, &merchant, 500_000),
+    ];
+    assert_eq!(
+        env.try_invoke_contract_check_auth::<AccountError>(
+            &client.address,
+            &payload,
+            signatures.into_val(&env),
+            &allowed,
+        ),
+        Ok(())
+    );
+
+    let denied = vec![&env, call_context(&env, &client.address, "withdraw")];
+    assert_eq!(
+        env.try_invoke_contract_check_auth::<AccountError>(
+            &client.address,
+            &payload,
+            signatures.into_val(&env),
+            &denied,
+        ),
+        Err(Ok(AccountError::NotPermitted))
+    );
+
+    // Revoking the backend also disables its signers.
+    client.set_backend_mode(&owner, &BackendMode::Revoked);
+    assert_eq!(
+        env.try_invoke_contract_check_auth::<AccountError>(
+            &client.address,
+            &payload,
+            signatures.into_val(&env),
+            &allowed,
+        ),
+        Err(Ok(AccountError::BackendRevoked))
+    );
+}
+
+#[test]
+fn test_backend_signer_within_allowlist_and_limits() {
+    let env = Env::default();
+    let (client, owner) = setup_account_wallet(&env);
+    let (key, signer) = ed25519_signer(&env, 7);
+    client.add_signer(&owner, &signer, &SignerRole::Backend);
+    client.set_spending_limits(
+        &client.get_backend(),
+        &SpendingLimits {
+            per_tx_max: 10_000_000,
+            daily_cap: 15_000_000,
+            window_ledgers: 17_280,
+        },
+        &BytesN::from_array(&env, &[67; 32]),
+    );
+
+    let payload = BytesN::from_array(&env, &[9; 32]);
+    let signatures = vec![&env, sign_payload(&env, &key, &signer, &payload)];
+    let merchant = Address::generate(&env);
+    let check = |payment_amount: i128, recipient: &Address| {
+        env.try_invoke_contract_check_auth::<AccountError>(
+            &client.address,
+            &payload,
+            signatures.into_val(&env),
+            &vec![
+                &env,
+                transfer_context(&env, &client.address, payment_amount, recipient, 500_000),
+            ],
+        )
+    };
+
+    // Amount plus max fee counts against the limits.
+    assert!(check(9_600_000, &merchant).is_err());
+    assert_eq!(check(9_000_000, &merchant), Ok(()));
+    assert!(check(6_000_000, &merchant).is_err());
+    assert_eq!(client.get_remaining_allowance(), Some(5_500_000));
+
+    client.set_allowlist_enabled(&owner, &true);
+    assert_eq!(
+        check(1_000_000, &merchant),
+        Err(Ok(AccountError::NotPermitted))
+    );
+}
+
+#[test]
+fn test_check_auth_rejects_unknown_and_duplicate_signers() {
+    let env = Env::default();
+    let (client, owner) = setup_account_wallet(&env);
+    let (key, signer) = ed25519_signer(&env, 3);
+    let payload = BytesN::from_array(&env, &[5; 32]);
+    let contexts = vec![
+        &env,
+        call_context(&env, &client.address, "transfer_to_vault"),
+    ];
+    let signature = sign_payload(&env, &key, &signer, &payload);
+
+    assert_eq!(
+        env.try_invoke_contract_check_auth::<AccountError>(
+            &client.address,
+            &payload,
+            vec![&env, signature.clone()].into_val(&env),
+            &contexts,
+        ),
+        Err(Ok(AccountError::UnknownSigner))
+    );
+
+    client.add_signer(&owner, &signer, &SignerRole::Owner);
+    assert_eq!(
+        env.try_invoke_contract_check_auth::<AccountError>(
+            &client.address,
+            &payload,
+            vec![&env, signature.clone(), signature].into_val(&env),
+            &contexts,
+        ),
+        Err(Ok(AccountError::DuplicateSigner))
+    );
+
+    client.remove_signer(&owner, &signer);
+    assert_eq!(client.get_signer(&signer), None);
+}
+
+fn passkey(env: &Env, seed: u8) -> (p256::ecdsa::SigningKey, BytesN<65>) {
+    let key = p256::ecdsa::SigningKey::from_slice(&[seed; 32]).unwrap();
+    let point = key.verifying_key().to_encoded_point(false);
+    let public_key = BytesN::from_array(env, point.as_bytes().try_into().unwrap());
+    (key, public_key)
+}
+
+fn webauthn_assertion(
+    env: &Env,
+    key: &p256::ecdsa::SigningKey,
+    challenge: &[u8; 32],
+    flags: u8,
+) -> WebAuthnAssertion {
+    let client_data_json = std::format!(
+        r#"{{"type":"webauthn.get","challenge":"{}","origin":"https://app.example","crossOrigin":false}}"#,
+        URL_SAFE_NO_PAD.encode(challenge)
+    );
+    let client_data_json = Bytes::from_slice(env, client_data_json.as_bytes());
+
+    let mut authenticator_data = [0u8; 37];
+    authenticator_data[32] = flags;
+
+    let mut message = authenticator_data.to_vec();
+    message.extend_from_slice(&env.crypto().sha256(&client_data_json).to_array());
+    let signature: p256::ecdsa::Signature = p256::ecdsa::signature::Signer::sign(key, &message);
+    let signature = signature.normalize_s().unwrap_or(signature);
+
+    WebAuthnAssertion {
+        authenticator_data: Bytes::from_slice(env, &authenticator_data),
+        client_data_json,
+        signature: BytesN::from_array(env, &signature.to_bytes().into()),
+    }
+}
+
+/// Attach a passkey-signed authorization for `wallet.fn_name(args)` to the next call.
+fn authorize_with_passkey(
+    env: &Env,
+    wallet: &Address,
+    fn_name: &str,
+    args: Vec<Val>,
+    key: &p256::ecdsa::SigningKey,
+    public_key: &BytesN<65>,
+) {
+    let nonce = 1;
+    let signature_expiration_ledger = env.ledger().sequence() + 100;
+    let root_invocation = xdr::SorobanAuthorizedInvocation {
+        function: xdr::SorobanAuthorizedFunction::ContractFn(xdr::InvokeContractArgs {
+            contract_address: wallet.into(),
+            function_name: fn_name.try_into().unwrap(),
+            args: args.into(),
+        }),
+        sub_invocations: Default::default(),
+    };
+    let preimage =
+        xdr::HashIdPreimage::SorobanAuthorization(xdr::HashIdPreimageSorobanAuthorization {
+            network_id: env.ledger().network_id().to_array().into(),
+            nonce,
+            signature_expiration_ledger,
+            invocation: root_invocation.clone(),
+        });
+    let preimage = Bytes::from_slice(env, &preimage.to_xdr(Limits::none()).unwrap());
+    let payload = env.crypto().sha256(&preimage).to_array();
+
+    let signatures = vec![
+        env,
+        AccountSignature {
+            signer: SignerKey::Secp256r1(public_key.clone()),
+            proof: SignatureProof::WebAuthn(webauthn_assertion(env, key, &payload, 0x05)),
+        },
+    ];
+    env.set_auths(&[xdr::SorobanAuthorizationEntry {
+        credentials: xdr::SorobanCredentials::Address(xdr::SorobanAddressCredentials {
+            address: wallet.into(),
+            nonce,
+            signature_expiration_ledger,
+            signature: xdr::ScVal::try_from_val(env, &signatures.to_val()).unwrap(),
+        }),
+        root_invocation,
+    }]);
+}
+
+fn setup_passkey_wallet(
+    env: &Env,
+) -> (
+    UserWalletClient<'static>,
+    Address,
+    p256::ecdsa::SigningKey,
+    BytesN<65>,
+) {
+    env.mock_all_auths();
+
+    let backend = Address::generate(env);
+    let vault = Address::generate(env);
+    let token_admin = Address::generate(env);
+    let usdc = env
+        .register_stellar_asset_contract_v2(token_admin)
+        .address();
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(env, &contract_id);
+    token::StellarAssetClient::new(env, &usdc).mint(&contract_id, &1000);
+
+    let (key, public_key) = passkey(env, 7);
+    client.set_passkey_owner(&backend, &public_key, &BytesN::from_array(env, &[68; 32]));
+    (client, usdc, key, public_key)
+}
+
+#[test]
+fn test_set_passkey_owner() {
+    let env = Env::default();
+    let (client, _, _, public_key) = setup_passkey_wallet(&env);
+
+    assert_eq!(client.get_owner(), Some(client.address.clone()));
+    assert_eq!(client.get_owner_passkey(), Some(public_key.clone()));
+    assert_eq!(
+        client.get_signer(&SignerKey::Secp256r1(public_key)),
+        Some(SignerRole::Owner)
+    );
+}
+
+#[test]
+fn test_passkey_owner_emergency_withdraw_pays_out() {
+    let env = Env::default();
+    let (client, usdc, _, _) = setup_passkey_wallet(&env);
+    let wallet = client.address.clone();
+
+    let executable_ledger = client.request_emergency_withdraw(&wallet);
+    env.ledger().set_sequence_number(executable_ledger);
+    let result = client.try_emergency_withdraw(&wallet);
+    assert!(result.is_err());
+
+    let payout = Address::generate(&env);
+    client.set_payout_address(&wallet, &payout);
+    client.emergency_withdraw(&wallet);
+
+    let token_client = token::Client::new(&env, &usdc);
+    assert_eq!(token_client.balance(&payout), 1000);
+    assert_eq!(token_client.balance(&wallet), 0);
+}
+
+#[test]
+#[should_panic(expected = "Payout address not set")]
+fn test_passkey_owner_close_requires_payout() {
+    let env = Env::default();
+    let (client, _, _, _) = setup_passkey_wallet(&env);
+
+    client.close(&client.address);
+}
+
+#[test]
+fn test_passkey_owner_close_pays_out() {
+    let env = Env::default();
+    let (client, usdc, _, _) = setup_passkey_wallet(&env);
+    let wallet = client.address.clone();
+    let payout = Address::generate(&env);
+
+    client.set_payout_address(&wallet, &payout);
+    client.close(&wallet);
+
+    assert_eq!(client.get_status(), WalletStatus::Closed);
+    assert_eq!(token::Client::new(&env, &usdc).balance(&payout), 1000);
+}
+
+#[test]
+#[should_panic(expected = "Invalid payout address")]
+fn test_payout_address_cannot_be_wallet() {
+    let env = Env::default();
+    let (client, _, _, _) = setup_passkey_wallet(&env);
+
+    client.set_payout_address(&client.address, &client.address);
+}
+
+#[test]
+fn test_passkey_owner_withdraws_with_webauthn_assertion() {
+    let env = Env::default();
+    let (client, usdc, key, public_key) = setup_passkey_wallet(&env);
+    let recipient = Address::generate(&env);
+
+    authorize_with_passkey(
+        &env,
+        &client.address,
+        "withdraw",
+        (
+            client.address.clone(),
+            400_i128,
+            recipient.clone(),
+            BytesN::from_array(&env, &[23; 32]),
+        )
+            .into_val(&env),
+        &key,
+        &public_key,
+    );
+    client.withdraw(
+        &client.address,
+        &400,
+        &recipient,
+        &BytesN::from_array(&env, &[23; 32]),
+    );
+
+    assert_eq!(token::Client::new(&env, &usdc).balance(&recipient), 400);
+    assert_eq!(client.get_balance(), 600);
+}
+
+#[test]
+#[should_panic]
+fn test_passkey_withdraw_rejects_other_key() {
+    let env = Env::default();
+    let (client, _, _, public_key) = setup_passkey_wallet(&env);
+    let (other_key, _) = passkey(&env, 8);
+    let recipient = Address::generate(&env);
+
+    authorize_with_passkey(
+        &env,
+        &client.address,
+        "withdraw",
+        (
+            client.address.clone(),
+            400_i128,
+            recipient.clone(),
+            BytesN::from_array(&env, &[24; 32]),
+        )
+            .into_val(&env),
+        &other_key,
+        &public_key,
+    );
+    client.withdraw(
+        &client.address,
+        &400,
+        &recipient,
+        &BytesN::from_array(&env, &[24; 32]),
+    );
+}
+
+#[test]
+fn test_webauthn_assertion_checks() {
+    let env = Env::default();
+    let (client, _, key, public_key) = setup_passkey_wallet(&env);
+    let payload = BytesN::from_array(&env, &[3; 32]);
+    let contexts = vec![&env, call_context(&env, &client.address, "withdraw")];
+    let signer = SignerKey::Secp256r1(public_key);
+    let check = |assertion: WebAuthnAssertion| {
+        let signatures = vec![
+            &env,
+            AccountSignature {
+                signer: signer.clone(),
+                proof: SignatureProof::WebAuthn(assertion),
+            },
+        ];
+        env.try_invoke_contract_check_auth::<AccountError>(
+            &client.address,
+            &payload,
+            signatures.into_val(&env),
+            &contexts,
+        )
+    };
+
+    let valid = webauthn_assertion(&env, &key, &payload.to_array(), 0x05);
+    assert_eq!(check(valid), Ok(()));
+
+    let wrong_challenge = webauthn_assertion(&env, &key, &[4; 32], 0x05);
+    assert_eq!(
+        check(wrong_challenge),
+        Err(Ok(AccountError::InvalidClientData))
+    );
+
+    let user_not_present = webauthn_assertion(&env, &key, &payload.to_array(), 0x00);
+    assert_eq!(
+        check(user_not_present),
+        Err(Ok(AccountError::InvalidAuthenticatorData))
+    );
+}
+
+#[test]
+fn test_rotate_away_from_passkey_removes_signer() {
+    let env = Env::default();
+    let (client, _, _, public_key) = setup_passkey_wallet(&env);
+    let new_owner = Address::generate(&env);
+
+    client.rotate_owner(&client.address, &new_owner);
+
+    assert_eq!(client.get_owner(), Some(new_owner));
+    assert_eq!(client.get_owner_passkey(), None);
+    assert_eq!(client.get_signer(&SignerKey::Secp256r1(public_key)), None);
+}
+
+fn spend_context(env: &Env, wallet: &Address, fn_name: &str, amount: i128) -> Context {
+    Context::Contract(ContractContext {
+        contract: wallet.clone(),
+        fn_name: Symbol::new(env, fn_name),
+        args: (wallet.clone(), amount).into_val(env),
+    })
+}
+
+#[test]
+fn test_session_key_scope_and_spend_cap() {
+    let env = Env::default();
+    let (client, owner) = setup_account_wallet(&env);
+    let (key, signer) = ed25519_signer(&env, 4);
+    let allowed_fns = vec![&env, Symbol::new(&env, "transfer_to_vault")];
+    client.add_session_key(&owner, &signer, &allowed_fns, &500, &100);
+
+    assert_eq!(
+        client.get_session_keys(),
+        vec![
+            &env,
+            SessionKey {
+                key: signer.clone(),
+                allowed_fns,
+                spend_cap: 500,
+                spent: 0,
+                expiry_ledger: 100,
+            }
+        ]
+    );
+
+    let payload = BytesN::from_array(&env, &[6; 32]);
+    let check = |context: Context| {
+        env.try_invoke_contract_check_auth::<AccountError>(
+            &client.address,
+            &payload,
+            vec![&env, sign_payload(&env, &key, &signer, &payload)].into_val(&env),
+            &vec![&env, context],
+        )
+    };
+
+    // The max fee of a vault payment counts against the cap too.
+    let merchant = Address::generate(&env);
+    let transfer = |amount| transfer_context(&env, &client.address, amount, &merchant, 50);
+    assert_eq!(check(transfer(250)), Ok(()));
+    assert_eq!(client.get_session_key(&signer).unwrap().spent, 300);
+    assert_eq!(
+        check(transfer(151)),
+        Err(Ok(AccountError::SpendCapExceeded))
+    );
+    assert_eq!(check(transfer(150)), Ok(()));
+
+    let withdraw = spend_context(&env, &client.address, "withdraw", 1);
+    assert_eq!(check(withdraw), Err(Ok(AccountError::NotPermitted)));
+
+    env.ledger().set_sequence_number(101);
+    assert_eq!(check(transfer(0)), Err(Ok(AccountError::SessionExpired)));
+}
+
+#[test]
+fn test_session_key_spends_settlement_asset_only() {
+    let env = Env::default();
+    let (client, owner) = setup_account_wallet(&env);
+    let (key, signer) = ed25519_signer(&env, 8);
+    let allowed_fns = vec![&env, Symbol::new(&env, "withdraw_asset")];
+    client.add_session_key(&owner, &signer, &allowed_fns, &500, &100);
+
+    let payload = BytesN::from_array(&env, &[6; 32]);
+    let check = |asset: &Address| {
+        let context = Context::Contract(ContractContext {
+            contract: client.address.clone(),
+            fn_name: Symbol::new(&env, "withdraw_asset"),
+            args: (
+                client.address.clone(),
+                asset.clone(),
+                100_i128,
+                owner.clone(),
+                BytesN::from_array(&env, &[1; 32]),
+            )
+                .into_val(&env),
+        });
+        env.try_invoke_contract_check_auth::<AccountError>(
+            &client.address,
+            &payload,
+            vec![&env, sign_payload(&env, &key, &signer, &payload)].into_val(&env),
+            &vec![&env, context],
+        )
+    };
+
+    let usdc = client.get_assets().get(0).unwrap();
+    assert_eq!(check(&usdc), Ok(()));
+    assert_eq!(
+        check(&Address::generate(&env)),
+        Err(Ok(AccountError::NotPermitted))
+    );
+    assert_eq!(client.get_session_key(&signer).unwrap().spent, 100);
+}
+
+#[test]
+fn test_revoke_session_key() {
+    let env = Env::default();
+    let (client, owner) = setup_account_wallet(&env);
+    let (key, signer) = ed25519_signer(&env, 5);
+    let allowed_fns = vec![&env, Symbol::new(&env, "transfer_to_vault")];
+    client.add_session_key(&owner, &signer, &allowed_fns, &500, &100);
+
+    client.revoke_session_key(&owner, &signer);
+    assert_eq!(client.get_session_keys(), vec![&env]);
+    assert_eq!(client.get_session_key(&signer), None);
+
+    let payload = BytesN::from_array(&env, &[8; 32]);
+    assert_eq!(
+        env.try_invoke_contract_check_auth::<AccountError>(
+            &client.address,
+            &payload,
+            vec![&env, sign_payload(&env, &key, &signer, &payload)].into_val(&env),
+            &vec![
+                &env,
+                spend_context(&env, &client.address, "transfer_to_vault", 1)
+            ],
+        ),
+        Err(Ok(AccountError::UnknownSigner))
+    );
+}
+
+#[test]
+#[should_panic(expected = "Expiry must be in the future")]
+fn test_session_key_requires_future_expiry() {
+    let env = Env::default();
+    let (client, owner) = setup_account_wallet(&env);
+    let (_, signer) = ed25519_signer(&env, 6);
+    env.ledger().set_sequence_number(50);
+
+    let allowed_fns = vec![&env, Symbol::new(&env, "transfer_to_vault")];
+    client.add_session_key(&owner, &signer, &allowed_fns, &500, &50);
+}
+
+fn setup_multi_asset_wallet(env: &Env) -> (UserWalletClient<'static>, Address, Address, Address) {
+    env.mock_all_auths();
+
+    let backend = Address::generate(env);
+    let vault = Address::generate(env);
+    let owner = Address::generate(env);
+    let usdc = env
+        .register_stellar_asset_contract_v2(Address::generate(env))
+        .address();
+    let eurc = env
+        .register_stellar_asset_contract_v2(Address::generate(env))
+        .address();
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &Some(owner.clone()),
+            &vec![env, eurc.clone(), usdc.clone()],
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(env, &contract_id);
+    token::StellarAssetClient::new(env, &usdc).mint(&contract_id, &1000);
+    token::StellarAssetClient::new(env, &eurc).mint(&contract_id, &300);
+    (client, owner, usdc, eurc)
+}
+
+#[test]
+fn test_get_balances() {
+    let env = Env::default();
+    let (client, _, usdc, eurc) = setup_multi_asset_wallet(&env);
+
+    assert_eq!(client.get_assets(), vec![&env, usdc.clone(), eurc.clone()]);
+    assert_eq!(
+        client.get_balances(),
+        vec![
+            &env,
+            AssetBalance {
+                asset: usdc,
+                balance: 1000
+            },
+            AssetBalance {
+                asset: eurc,
+                balance: 300
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_withdraw_asset() {
+    let env = Env::default();
+    let (client, owner, _, eurc) = setup_multi_asset_wallet(&env);
+    let recipient = Address::generate(&env);
+
+    client.withdraw_asset(
+        &owner,
+        &eurc,
+        &120,
+        &recipient,
+        &BytesN::from_array(&env, &[25; 32]),
+    );
+
+    assert_eq!(token::Client::new(&env, &eurc).balance(&recipient), 120);
+    assert_eq!(client.get_balance(), 1000);
+}
+
+#[test]
+#[should_panic(expected = "Asset not supported")]
+fn test_withdraw_unsupported_asset() {
+    let env = Env::default();
+    let (client, owner, _, eurc) = setup_multi_asset_wallet(&env);
+
+    client.remove_asset(&owner, &eurc);
+    client.withdraw_asset(
+        &owner,
+        &eurc,
+        &120,
+        &Address::generate(&env),
+        &BytesN::from_array(&env, &[26; 32]),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Backend can only withdraw settlement asset")]
+fn test_backend_cannot_withdraw_other_assets() {
+    let env = Env::default();
+    let (client, _, _, eurc) = setup_multi_asset_wallet(&env);
+
+    client.withdraw_asset(
+        &client.get_backend(),
+        &eurc,
+        &120,
+        &Address::generate(&env),
+        &BytesN::from_array(&env, &[27; 32]),
+    );
+}
+
+#[test]
+fn test_backend_cannot_remove_or_rescue_assets() {
+    let env = Env::default();
+    let (client, _, _, eurc) = setup_multi_asset_wallet(&env);
+    let backend = client.get_backend();
+
+    let result = client.try_remove_asset(&backend, &eurc);
+    assert!(result.is_err());
+    assert!(client.is_asset_supported(&eurc));
+
+    let result = client.try_rescue_token(
+        &backend,
+        &eurc,
+        &backend,
+        &300,
+        &BytesN::from_array(&env, &[28; 32]),
+    );
+    assert!(result.is_err());
+    assert_eq!(
+        token::Client::new(&env, &eurc).balance(&client.address),
+        300
+    );
+}
+
+#[test]
+#[should_panic(expected = "Cannot remove settlement asset")]
+fn test_remove_settlement_asset() {
+    let env = Env::default();
+    let (client, owner, usdc, _) = setup_multi_asset_wallet(&env);
+
+    client.remove_asset(&owner, &usdc);
+}
+
+#[test]
+fn test_emergency_withdraw_sweeps_all_assets() {
+    let env = Env::default();
+    let (client, owner, usdc, eurc) = setup_multi_asset_wallet(&env);
+
+    let executable_ledger = client.request_emergency_withdraw(&owner);
+    env.ledger().set_sequence_number(executable_ledger);
+    client.emergency_withdraw(&owner);
+
+    assert_eq!(token::Client::new(&env, &usdc).balance(&owner), 1000);
+    assert_eq!(token::Client::new(&env, &eurc).balance(&owner), 300);
+}
+
+#[contract]
+struct BrokenToken;
+
+#[contractimpl]
+impl BrokenToken {
+    pub fn balance(_env: Env, _id: Address) -> i128 {
+        panic!("broken token");
+    }
+}
+
+#[test]
+fn test_broken_asset_does_not_block_exit() {
+    let env = Env::default();
+    let (client, owner, usdc, eurc) = setup_multi_asset_wallet(&env);
+    let broken = env.register(BrokenToken, ());
+
+    let result = client.try_add_asset(
+        &client.get_backend(),
+        &broken,
+        &BytesN::from_array(&env, &[44; 32]),
+    );
+    assert!(result.is_err());
+
+    client.add_asset(&owner, &broken, &BytesN::from_array(&env, &[45; 32]));
+    assert_eq!(client.get_balances().len(), 2);
+
+    let executable_ledger = client.request_emergency_withdraw(&owner);
+    env.ledger().set_sequence_number(executable_ledger);
+    client.emergency_withdraw(&owner);
+
+    assert_eq!(token::Client::new(&env, &usdc).balance(&owner), 1000);
+    assert_eq!(token::Client::new(&env, &eurc).balance(&owner), 300);
+}
+
+#[test]
+fn test_transfer_to_vault_only_needs_backend_signature() {
+    let env = Env::default();
+
+    let backend = Address::generate(&env);
+    let admin = Address::generate(&env);
+    let usdc = env
+        .register_stellar_asset_contract_v2(Address::generate(&env))
+        .address();
+
+    let vault_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
+    let wallet_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let wallet = UserWalletClient::new(&env, &wallet_id);
+    env.mock_all_auths();
+    token::StellarAssetClient::new(&env, &usdc).mint(&wallet_id, &100_000_000);
+
+    // The wallet authorizes the vault's pull itself; only the backend signs.
+    let payment_id = BytesN::from_array(&env, &[2; 32]);
+    let recipient = Address::generate(&env);
+    env.mock_auths(&[MockAuth {
+        address: &backend,
+        invoke: &MockAuthInvoke {
+            contract: &wallet_id,
+            fn_name: "transfer_to_vault",
+            args: (
+                backend.clone(),
+                10_000_000_i128,
+                payment_id.clone(),
+                recipient.clone(),
+                500_000_i128,
+            )
+                .into_val(&env),
+            sub_invokes: &[],
+        },
+    }]);
+    let receipt =
+        wallet.transfer_to_vault(&backend, &10_000_000, &payment_id, &recipient, &500_000);
+
+    assert_eq!(receipt.total_amount, 10_500_000);
+    assert_eq!(
+        token::Client::new(&env, &usdc).balance(&vault_id),
+        10_500_000
+    );
+}
+
+#[test]
+#[should_panic(expected = "Fee exceeds max_fee")]
+fn test_transfer_to_vault_rejects_fee_above_quote() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let admin = Address::generate(&env);
+    let usdc = env
+        .register_stellar_asset_contract_v2(Address::generate(&env))
+        .address();
+
+    let vault_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
+    let wallet_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let wallet = UserWalletClient::new(&env, &wallet_id);
+    token::StellarAssetClient::new(&env, &usdc).mint(&wallet_id, &100_000_000);
+
+    // The user was shown the current fee without pinning it, then the admin
+    // raised the fee.
+    let vault = VaultClient::new(&env, &vault_id);
+    let shown_fee = vault.get_fee_amount();
+    vault.set_fee(&admin, &1_000_000);
+
+    wallet.transfer_to_vault(
+        &backend,
+        &10_000_000,
+        &BytesN::from_array(&env, &[1; 32]),
+        &Address::generate(&env),
+        &shown_fee,
+    );
+}
+
+#[test]
+fn test_transfer_to_vault_honors_pinned_quote() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let admin = Address::generate(&env);
+    let usdc = env
+        .register_stellar_asset_contract_v2(Address::generate(&env))
+        .address();
+
+    let vault_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
+    let wallet_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let wallet = UserWalletClient::new(&env, &wallet_id);
+    token::StellarAssetClient::new(&env, &usdc).mint(&wallet_id, &100_000_000);
+
+    // The quote pinned for this payment survives the fee raise.
+    let vault = VaultClient::new(&env, &vault_id);
+    let payment_id = BytesN::from_array(&env, &[1; 32]);
+    let quote = vault.quote_fee(&wallet_id, &wallet_id, &payment_id);
+    vault.set_fee(&admin, &1_000_000);
+
+    let receipt = wallet.transfer_to_vault(
+        &backend,
+        &10_000_000,
+        &payment_id,
+        &Address::generate(&env),
+        &quote.fee_amount,
+    );
+    assert_eq!(receipt.fee_amount, 500_000);
+    assert_eq!(vault.get_payment_fee(&wallet_id, &payment_id), 1_000_000);
+}
+
+#[test]
+fn test_was_executed_tracks_op_ids() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let usdc = env
+        .register_stellar_asset_contract_v2(Address::generate(&env))
+        .address();
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(&env, &contract_id);
+    token::StellarAssetClient::new(&env, &usdc).mint(&contract_id, &1000);
+
+    let op_id = BytesN::from_array(&env, &[42; 32]);
+    assert!(!client.was_executed(&op_id));
+
+    client.withdraw(&backend, &100, &Address::generate(&env), &op_id);
+    assert!(client.was_executed(&op_id));
+}
+
+#[test]
+#[should_panic(expected = "Operation already executed")]
+fn test_retried_add_asset_rejected() {
+    let env = Env::default();
+    let (client, owner, _, _) = setup_multi_asset_wallet(&env);
+    let op_id = BytesN::from_array(&env, &[43; 32]);
+
+    client.add_asset(&owner, &Address::generate(&env), &op_id);
+    client.add_asset(&owner, &Address::generate(&env), &op_id);
+}
+
+#[test]
+#[should_panic(expected = "Operation already executed")]
+fn test_retried_spending_limits_rejected() {
+    let env = Env::default();
+    let (client, _, _, _) = setup_multi_asset_wallet(&env);
+    let backend = client.get_backend();
+    let limits = SpendingLimits {
+        per_tx_max: 10_000_000,
+        daily_cap: 15_000_000,
+        window_ledgers: 17_280,
+    };
+    let op_id = BytesN::from_array(&env, &[44; 32]);
+
+    client.set_spending_limits(&backend, &limits, &op_id);
+    assert!(client.was_executed(&op_id));
+    client.set_spending_limits(&backend, &limits, &op_id);
+}
+
+#[test]
+#[should_panic(expected = "Operation already executed")]
+fn test_retried_withdrawal_rejected() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let usdc = env
+        .register_stellar_asset_contract_v2(Address::generate(&env))
+        .address();
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(&env, &contract_id);
+    token::StellarAssetClient::new(&env, &usdc).mint(&contract_id, &1000);
+
+    let op_id = BytesN::from_array(&env, &[42; 32]);
+    let recipient = Address::generate(&env);
+    client.withdraw(&backend, &100, &recipient, &op_id);
+    client.withdraw(&backend, &100, &recipient, &op_id);
+}
+
+#[test]
+#[should_panic(expected = "Operation already executed")]
+fn test_set_owner_op_id_not_reusable() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let usdc = Address::generate(&env);
+
+    let contract_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let client = UserWalletClient::new(&env, &contract_id);
+
+    let op_id = BytesN::from_array(&env, &[7; 32]);
+    client.set_owner(&backend, &Address::generate(&env), &op_id);
+    client.set_owner(&backend, &Address::generate(&env), &op_id);
+}
+
+fn setup_factory_wallet(env: &Env) -> (UserWalletClient<'static>, WalletFactoryClient<'static>) {
+    env.mock_all_auths();
+
+    let admin = Address::generate(env);
+    let backend = Address::generate(env);
+    let vault = Address::generate(env);
+    let usdc = env
+        .register_stellar_asset_contract_v2(Address::generate(env))
+        .address();
+    let wasm_hash = BytesN::from_array(env, &[0; 32]);
+
+    let factory_id = env.register(WalletFactory, (&admin, &backend, &vault, &usdc, &wasm_hash));
+    let wallet_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &vault,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(env),
+            &Some(factory_id.clone()),
+            &1u32,
+        ),
+    );
+    token::StellarAssetClient::new(env, &usdc).mint(&wallet_id, &1000);
+    (
+        UserWalletClient::new(env, &wallet_id),
+        WalletFactoryClient::new(env, &factory_id),
+    )
+}
+
+#[test]
+fn test_wallet_follows_factory_backend_and_vault() {
+    let env = Env::default();
+    let (wallet, factory) = setup_factory_wallet(&env);
+    let admin = factory.get_admin();
+    assert_eq!(wallet.get_factory(), Some(factory.address.clone()));
+
+    let new_backend = Address::generate(&env);
+    let new_vault = Address::generate(&env);
+    factory.update_backend(&admin, &new_backend);
+    factory.update_vault(&admin, &new_vault);
+
+    assert_eq!(wallet.get_backend(), new_backend);
+    assert_eq!(wallet.get_vault(), new_vault);
+
+    let recipient = Address::generate(&env);
+    wallet.withdraw(
+        &new_backend,
+        &100,
+        &recipient,
+        &BytesN::from_array(&env, &[1; 32]),
+    );
+    assert_eq!(wallet.get_balance(), 900);
+}
+
+#[test]
+#[should_panic(expected = "Not authorized")]
+fn test_rotated_out_backend_loses_access() {
+    let env = Env::default();
+    let (wallet, factory) = setup_factory_wallet(&env);
+    let old_backend = wallet.get_backend();
+
+    factory.update_backend(&factory.get_admin(), &Address::generate(&env));
+
+    wallet.withdraw(
+        &old_backend,
+        &100,
+        &Address::generate(&env),
+        &BytesN::from_array(&env, &[1; 32]),
+    );
+}
+
+#[test]
+fn test_owner_pinned_backend_ignores_factory() {
+    let env = Env::default();
+    let (wallet, factory) = setup_factory_wallet(&env);
+    let owner = Address::generate(&env);
+    wallet.set_owner(
+        &wallet.get_backend(),
+        &owner,
+        &BytesN::from_array(&env, &[1; 32]),
+    );
+
+    let own_backend = Address::generate(&env);
+    wallet.authorize_backend(&owner, &own_backend);
+    factory.update_backend(&factory.get_admin(), &Address::generate(&env));
+
+    assert_eq!(wallet.get_backend(), own_backend);
+}
+
+#[test]
+fn test_factory_wallet_starts_at_version_one() {
+    let env = Env::default();
+    let (wallet, _) = setup_factory_wallet(&env);
+
+    assert_eq!(wallet.get_version(), 1);
+    assert!(!wallet.is_upgrade_opted_out());
+}
+
+#[test]
+#[should_panic(expected = "Not a factory wallet")]
+fn test_upgrade_requires_factory() {
+    let env = Env::default();
+    let (wallet, _) = setup_account_wallet(&env);
+
+    wallet.upgrade(&BytesN::from_array(&env, &[1; 32]), &2);
+}
+
+#[test]
+#[should_panic(expected = "Version must increase")]
+fn test_upgrade_rejects_same_version() {
+    let env = Env::default();
+    let (wallet, _) = setup_factory_wallet(&env);
+
+    wallet.upgrade(&BytesN::from_array(&env, &[1; 32]), &1);
+}
+
+#[test]
+#[should_panic(expected = "Wallet opted out of upgrades")]
+fn test_opted_out_wallet_rejects_upgrade() {
+    let env = Env::default();
+    let (wallet, _) = setup_factory_wallet(&env);
+    let owner = Address::generate(&env);
+    wallet.set_owner(
+        &wallet.get_backend(),
+        &owner,
+        &BytesN::from_array(&env, &[1; 32]),
+    );
+
+    wallet.set_upgrade_opt_out(&owner, &true);
+    assert!(wallet.is_upgrade_opted_out());
+
+    wallet.upgrade(&BytesN::from_array(&env, &[1; 32]), &2);
+}
+
+#[test]
+#[should_panic(expected = "Only owner")]
+fn test_backend_cannot_opt_out_of_upgrades() {
+    let env = Env::default();
+    let (wallet, _) = setup_factory_wallet(&env);
+    wallet.set_owner(
+        &wallet.get_backend(),
+        &Address::generate(&env),
+        &BytesN::from_array(&env, &[1; 32]),
+    );
+
+    wallet.set_upgrade_opt_out(&wallet.get_backend(), &true);
+}
+
+#[test]
+fn test_factory_upgrade_skips_ineligible_wallets() {
+    let env = Env::default();
+    let (wallet, factory) = setup_factory_wallet(&env);
+    let admin = factory.get_admin();
+    let owner = Address::generate(&env);
+    wallet.set_owner(
+        &wallet.get_backend(),
+        &owner,
+        &BytesN::from_array(&env, &[1; 32]),
+    );
+    wallet.set_upgrade_opt_out(&owner, &true);
+
+    // Already on the factory's current version
+    let results = factory.upgrade_wallets(&admin, &vec![&env, wallet.address.clone()]);
+    assert_eq!(results, vec![&env, false]);
+
+    // Newer code is available, but the owner opted out
+    factory.set_wallet_wasm(&admin, &BytesN::from_array(&env, &[2; 32]));
+    let results = factory.upgrade_wallets(&admin, &vec![&env, wallet.address.clone()]);
+    assert_eq!(results, vec![&env, false]);
+    assert_eq!(wallet.get_version(), 1);
+}
+
+/// Record the wallet in the factory's index as if `create_wallet` had deployed it.
+fn index_in_factory(
+    env: &Env,
+    wallet: &UserWalletClient,
+    factory: &WalletFactoryClient,
+) -> BytesN<32> {
+    let user_id_hash = BytesN::from_array(env, &[9; 32]);
+    env.as_contract(&factory.address, || {
+        let storage = env.storage().persistent();
+        storage.set(
+            &FactoryKey::UserWallet(user_id_hash.clone()),
+            &wallet.address,
+        );
+        storage.set(
+            &FactoryKey::WalletUser(wallet.address.clone()),
+            &user_id_hash,
+        );
+    });
+    user_id_hash
+}
+
+#[test]
+#[should_panic(expected = "Wallet is frozen")]
+fn test_frozen_wallet_blocks_withdrawals() {
+    let env = Env::default();
+    let (wallet, factory) = setup_factory_wallet(&env);
+    index_in_factory(&env, &wallet, &factory);
+
+    factory.freeze_wallet(&factory.get_admin(), &wallet.address);
+    assert_eq!(wallet.get_status(), WalletStatus::Frozen);
+
+    wallet.withdraw(
+        &wallet.get_backend(),
+        &100,
+        &Address::generate(&env),
+        &BytesN::from_array(&env, &[1; 32]),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Wallet is frozen")]
+fn test_frozen_wallet_blocks_transfer_to_vault() {
+    let env = Env::default();
+    let (wallet, _) = setup_factory_wallet(&env);
+    wallet.set_frozen(&true);
+
+    wallet.transfer_to_vault(
+        &wallet.get_backend(),
+        &100,
+        &BytesN::from_array(&env, &[1; 32]),
+        &Address::generate(&env),
+        &0,
+    );
+}
+
+#[test]
+fn test_frozen_wallet_owner_signer_cannot_move_tokens_directly() {
+    let env = Env::default();
+    let (wallet, factory) = setup_factory_wallet(&env);
+    index_in_factory(&env, &wallet, &factory);
+    let owner = Address::generate(&env);
+    wallet.set_owner(
+        &wallet.get_backend(),
+        &owner,
+        &BytesN::from_array(&env, &[1; 32]),
+    );
+    let (key, signer) = ed25519_signer(&env, 1);
+    wallet.add_signer(&owner, &signer, &SignerRole::Owner);
+
+    let usdc = wallet.get_assets().get(0).unwrap();
+    let payload = BytesN::from_array(&env, &[7; 32]);
+    let signatures = vec![&env, sign_payload(&env, &key, &signer, &payload)];
+    let token_transfer = vec![
+        &env,
+        Context::Contract(ContractContext {
+            contract: usdc,
+            fn_name: Symbol::new(&env, "transfer"),
+            args: (wallet.address.clone(), Address::generate(&env), 1000i128).into_val(&env),
+        }),
+    ];
+    assert_eq!(
+        env.try_invoke_contract_check_auth::<AccountError>(
+            &wallet.address,
+            &payload,
+            signatures.into_val(&env),
+            &token_transfer,
+        ),
+        Ok(())
+    );
+
+    factory.freeze_wallet(&factory.get_admin(), &wallet.address);
+    assert_eq!(
+        env.try_invoke_contract_check_auth::<AccountError>(
+            &wallet.address,
+            &payload,
+            signatures.into_val(&env),
+            &token_transfer,
+        ),
+        Err(Ok(AccountError::WalletNotActive))
+    );
+
+    // Calls into the wallet itself still pass and hit its own status checks.
+    let own_call = vec![&env, call_context(&env, &wallet.address, "withdraw")];
+    assert_eq!(
+        env.try_invoke_contract_check_auth::<AccountError>(
+            &wallet.address,
+            &payload,
+            signatures.into_val(&env),
+            &own_call,
+        ),
+        Ok(())
+    );
+}
+
+#[test]
+fn test_compliance_exit_and_unfreeze() {
+    let env = Env::default();
+    let (wallet, factory) = setup_factory_wallet(&env);
+    index_in_factory(&env, &wallet, &factory);
+    let compliance = Address::generate(&env);
+    factory.grant_role(&factory.get_admin(), &compliance, &COMPLIANCE_ROLE);
+
+    factory.freeze_wallet(&compliance, &wallet.address);
+    let exit_to = Address::generate(&env);
+    factory.approve_exit(&compliance, &wallet.address, &exit_to);
+
+    let usdc = wallet.get_assets().get(0).unwrap();
+    assert_eq!(token::Client::new(&env, &usdc).balance(&exit_to), 1000);
+    assert_eq!(wallet.get_balance(), 0);
+    assert_eq!(wallet.get_status(), WalletStatus::Frozen);
+
+    factory.unfreeze_wallet(&compliance, &wallet.address);
+    assert_eq!(wallet.get_status(), WalletStatus::Active);
+}
+
+#[test]
+#[should_panic(expected = "Wallet is not frozen")]
+fn test_compliance_exit_requires_frozen_wallet() {
+    let env = Env::default();
+    let (wallet, factory) = setup_factory_wallet(&env);
+    index_in_factory(&env, &wallet, &factory);
+
+    factory.approve_exit(
+        &factory.get_admin(),
+        &wallet.address,
+        &Address::generate(&env),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Not a factory wallet")]
+fn test_freeze_unknown_wallet() {
+    let env = Env::default();
+    let (wallet, factory) = setup_factory_wallet(&env);
+
+    factory.freeze_wallet(&factory.get_admin(), &wallet.address);
+}
+
+#[test]
+fn test_close_sweeps_to_owner_and_frees_user() {
+    let env = Env::default();
+    let (wallet, factory) = setup_factory_wallet(&env);
+    let user_id_hash = index_in_factory(&env, &wallet, &factory);
+    let owner = Address::generate(&env);
+    wallet.set_owner(
+        &wallet.get_backend(),
+        &owner,
+        &BytesN::from_array(&env, &[1; 32]),
+    );
+    let next_address = factory.predict_wallet_address(&user_id_hash);
+
+    wallet.close(&owner);
+
+    let usdc = wallet.get_assets().get(0).unwrap();
+    assert_eq!(token::Client::new(&env, &usdc).balance(&owner), 1000);
+    assert_eq!(wallet.get_status(), WalletStatus::Closed);
+    assert!(!factory.has_wallet(&user_id_hash));
+    assert!(factory.is_user_closed(&user_id_hash));
+    assert!(factory.is_factory_wallet(&wallet.address));
+    assert_ne!(factory.predict_wallet_address(&user_id_hash), next_address);
+}
+
+#[test]
+#[should_panic(expected = "Wallet is closed")]
+fn test_closed_wallet_cannot_be_unfrozen() {
+    let env = Env::default();
+    let (wallet, factory) = setup_factory_wallet(&env);
+    index_in_factory(&env, &wallet, &factory);
+    let owner = Address::generate(&env);
+    wallet.set_owner(
+        &wallet.get_backend(),
+        &owner,
+        &BytesN::from_array(&env, &[1; 32]),
+    );
+    wallet.close(&owner);
+
+    factory.unfreeze_wallet(&factory.get_admin(), &wallet.address);
+}
+
+#[test]
+#[should_panic(expected = "Only owner")]
+fn test_backend_cannot_approve_vault() {
+    let env = Env::default();
+    let (wallet, _) = setup_factory_wallet(&env);
+    wallet.set_owner(
+        &wallet.get_backend(),
+        &Address::generate(&env),
+        &BytesN::from_array(&env, &[1; 32]),
+    );
+
+    wallet.approve_vault(
+        &wallet.get_backend(),
+        &500,
+        &1000,
+        &BytesN::from_array(&env, &[2; 32]),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Wallet is frozen")]
+fn test_frozen_wallet_cannot_be_closed() {
+    let env = Env::default();
+    let (wallet, _) = setup_factory_wallet(&env);
+    let owner = Address::generate(&env);
+    wallet.set_owner(
+        &wallet.get_backend(),
+        &owner,
+        &BytesN::from_array(&env, &[1; 32]),
+    );
+    wallet.set_frozen(&true);
+
+    wallet.close(&owner);
+}
+
+#[test]
+fn test_freeze_revokes_vault_allowance() {
+    let env = Env::default();
+    let (wallet, factory) = setup_factory_wallet(&env);
+    index_in_factory(&env, &wallet, &factory);
+    let usdc = wallet.get_assets().get(0).unwrap();
+    let vault = wallet.get_vault();
+    let owner = Address::generate(&env);
+    wallet.set_owner(
+        &wallet.get_backend(),
+        &owner,
+        &BytesN::from_array(&env, &[1; 32]),
+    );
+
+    wallet.approve_vault(&owner, &500, &1000, &BytesN::from_array(&env, &[2; 32]));
+    let usdc_client = token::Client::new(&env, &usdc);
+    assert_eq!(usdc_client.allowance(&wallet.address, &vault), 500);
+
+    factory.freeze_wallet(&factory.get_admin(), &wallet.address);
+    assert_eq!(usdc_client.allowance(&wallet.address, &vault), 0);
+}
+
+#[test]
+fn test_any_factory_backend_operator_can_act() {
+    let env = Env::default();
+    let (wallet, factory) = setup_factory_wallet(&env);
+    let operator = Address::generate(&env);
+    factory.grant_role(&factory.get_admin(), &operator, &BACKEND_ROLE);
+
+    let recipient = Address::generate(&env);
+    wallet.withdraw(
+        &operator,
+        &100,
+        &recipient,
+        &BytesN::from_array(&env, &[1; 32]),
+    );
+    assert_eq!(wallet.get_balance(), 900);
+
+    factory.revoke_role(&factory.get_admin(), &operator, &BACKEND_ROLE);
+    let result = wallet.try_withdraw(
+        &operator,
+        &100,
+        &recipient,
+        &BytesN::from_array(&env, &[2; 32]),
+    );
+    assert!(result.is_err());
 }
@@ -1,15 +1,25 @@
 #![no_std]
 
-mod access_control;
 mod test;
 mod token_helpers;
 
 use soroban_sdk::{
-    contract, contractevent, contractimpl, contracttype, token, Address, BytesN, Env, Symbol,
-    Vec,
+    contract, contractevent, contractimpl, contracttype, token, Address, Bytes, BytesN, Env,
+    IntoVal, Symbol, Val, Vec,
 };
 
+/// Linear release schedule for a streaming payout, in ledger sequence numbers.
+#[contracttype]
+#[derive(Clone)]
+pub struct VestingSchedule {
+    pub payer: Address,
+    pub start_ledger: u32,
+    pub end_ledger: u32,
+    pub cliff_ledger: Option<u32>,
+}
+
 /// Pending claim record: amounts reserved and expiry ledger for cancellation rules.
+/// Streaming payouts track `claimed_amount` to date against their `VestingSchedule`.
 #[contracttype]
 #[derive(Clone)]
 pub struct PendingClaim {
@@ -17,28 +27,73 @@ pub struct PendingClaim {
     pub payment_amount: i128,
     pub fee_amount: i128,
     pub expiry_ledger: u32,
+    pub claimed_amount: i128,
+}
+
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScheduleStatus {
+    Active,
+    Paused,
+    Cancelled,
+    Completed,
 }
 
+/// Recurring payment pulled from the payer through a token allowance every
+/// `interval_ledgers`, at most `max_cycles` times.
 #[contracttype]
 #[derive(Clone)]
-pub struct PendingClaim {
-    pub payment_amount: i128,
+pub struct RecurringSchedule {
+    pub payer: Address,
+    pub recipient: Address,
+    pub amount: i128,
+    pub interval_ledgers: u32,
+    pub max_cycles: u32,
+    pub cycles_executed: u32,
+    pub next_ledger: u32,
+    pub status: ScheduleStatus,
+}
+
+/// Snapshot of the vault's token balance against its accounting buckets.
+/// `surplus` is the balance above `available_payments + available_fees`;
+/// a negative value is a deficit.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountingReport {
+    pub token_balance: i128,
+    pub available_payments: i128,
+    pub total_payments: i128,
+    pub available_fees: i128,
+    pub total_fees: i128,
+    pub open_claims: i128,
+    pub surplus: i128,
+}
+
+/// Fee pinned for one wallet payment, honored until `valid_until_ledger`
+/// even if the admin changes the fee in the meantime.
+#[contracttype]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeeQuote {
     pub fee_amount: i128,
-    pub expiry_ledger: u32,
+    pub valid_until_ledger: u32,
 }
 
+/// Result of a wallet-funded payment, returned to the paying wallet.
 #[contracttype]
-#[derive(Clone)]
-pub struct PendingClaim {
+#[derive(Clone, Debug, PartialEq)]
+pub struct PaymentReceipt {
+    pub payment_id: BytesN<32>,
+    pub payer: Address,
+    pub recipient: Address,
     pub payment_amount: i128,
     pub fee_amount: i128,
+    pub total_amount: i128,
     pub expiry_ledger: u32,
 }
 
 #[contracttype]
 #[derive(Clone)]
 pub enum DataKey {
-    Admin,
     Roles(Address),
     UsdcToken,
     FeeAmount,
@@ -48,10 +103,23 @@ pub enum DataKey {
     AvailableFees,
     TotalFees,
     Paused,
-    PendingClaim(BytesN<32>),
+    PendingClaim(BytesN<32>), // persistent
+    OpenClaimsTotal,
+    VestingSchedule(BytesN<32>),    // persistent
+    PendingClaimCount,              // persistent: open claims in the index
+    PendingClaimAt(u32),            // persistent: index position -> payment id
+    PendingClaimPos(BytesN<32>),    // persistent: payment id -> index position
+    RecipientClaimCount(Address),   // persistent: open claims of a recipient
+    RecipientClaimAt(Address, u32), // persistent: recipient position -> payment id
+    RecipientClaimPos(BytesN<32>),  // persistent: payment id -> recipient position
+    FeeQuote(Address, BytesN<32>),  // temporary: fee pinned for a payer's payment id
+    Schedule(u64),                  // persistent; removed once Completed or Cancelled
+    NextScheduleId,
 }
 
 const MAX_FEE: i128 = 5_000_000;
+/// How long a fee quote is honored before re-quoting (~5 minutes).
+const FEE_QUOTE_VALIDITY_LEDGERS: u32 = 60;
 
 #[contractevent(topics = ["VAULT", "payment"])]
 struct PaymentProcessedEvent {
@@ -75,6 +143,19 @@ struct VaultFundsWithdrawnEvent {
     amount: i128,
 }
 
+#[contractevent(topics = ["VAULT", "surplus"])]
+struct SurplusRecoveredEvent {
+    to: Address,
+    amount: i128,
+}
+
+#[contractevent(topics = ["VAULT", "rescue"])]
+struct TokenRescuedEvent {
+    token: Address,
+    to: Address,
+    amount: i128,
+}
+
 #[contractevent(topics = ["VAULT", "config"])]
 struct FeeUpdatedEvent {
     old_fee: i128,
@@ -104,8 +185,71 @@ struct PaymentCancelledEvent {
     force: bool,
 }
 
+#[contractevent(topics = ["VAULT", "vesting"])]
+struct VestingPaymentCreatedEvent {
+    payer: Address,
+    recipient: Address,
+    payment_id: BytesN<32>,
+    payment_amount: i128,
+    fee_amount: i128,
+    start_ledger: u32,
+    end_ledger: u32,
+    cliff_ledger: Option<u32>,
+}
+
+#[contractevent(topics = ["VAULT", "vest_cncl"])]
+struct VestingPaymentCancelledEvent {
+    payment_id: BytesN<32>,
+    recipient_amount: i128,
+    refund_amount: i128,
+    cancelled_by: Address,
+}
+
+#[contractevent(topics = ["VAULT", "sched_new"])]
+struct ScheduleCreatedEvent {
+    schedule_id: u64,
+    payer: Address,
+    recipient: Address,
+    amount: i128,
+    interval_ledgers: u32,
+    max_cycles: u32,
+}
+
+#[contractevent(topics = ["VAULT", "sched_exec"])]
+struct ScheduleExecutedEvent {
+    schedule_id: u64,
+    payment_id: BytesN<32>,
+    cycle: u32,
+    amount: i128,
+    fee_amount: i128,
+}
+
+#[contractevent(topics = ["VAULT", "sched_upd"])]
+struct ScheduleStatusUpdatedEvent {
+    schedule_id: u64,
+    status: ScheduleStatus,
+}
+
+#[contractevent(topics = ["VAULT", "role"])]
+pub struct RoleGrantedEvent {
+    pub account: Address,
+    pub role: Symbol,
+}
+
+#[contractevent(topics = ["VAULT", "role"])]
+pub struct RoleRevokedEvent {
+    pub account: Address,
+    pub role: Symbol,
+}
+
 /// Ledgers after process_payment after which a claim can be cancelled without force.
 const CLAIM_EXPIRY_LEDGERS: u32 = 10000;
+/// TTL recurring schedules are extended to when written (~180 days).
+const SCHEDULE_TTL_LEDGERS: u32 = 3_110_400;
+/// TTL pending claims and their index entries are extended to when written (~180 days).
+const CLAIM_TTL_LEDGERS: u32 = 3_110_400;
+/// Most payment ids the pending claim listings return in one page.
+const MAX_PAGE_SIZE: u32 = 100;
 
 #[contract]
 pub struct Vault;
@@ -123,7 +267,7 @@ impl Vault {
             panic!("Fee exceeds maximum");
         }
 
-        env.storage().instance().set(&DataKey::Admin, &admin);
+        access_control::set_primary(&env, access_control::ADMIN_ROLE, &admin);
         env.storage()
             .instance()
             .set(&DataKey::UsdcToken, &usdc_token);
@@ -143,14 +287,12 @@ impl Vault {
             .instance()
             .set(&DataKey::AvailableFees, &0i128);
         env.storage().instance().set(&DataKey::TotalFees, &0i128);
-        env.storage().instance().set(&DataKey::Paused, &false);
-
-        let empty_vec: Vec<BytesN<32>> = Vec::new(&env);
         env.storage()
             .instance()
-            .set(&DataKey::AllPendingClaims, &empty_vec);
+            .set(&DataKey::OpenClaimsTotal, &0i128);
+        env.storage().instance().set(&DataKey::Paused, &false);
 
-        access_control::grant_role(&env, admin, access_control::ADMIN_ROLE);
+        Self::grant(&env, admin, access_control::ADMIN_ROLE);
     }
 
     pub fn process_payment(
@@ -175,89 +317,247 @@ impl Vault {
         if payment_amount <= 0 {
             panic!("Payment amount must be > 0");
         }
+        if env
+            .storage()
+            .persistent()
+            .has(&DataKey::PendingClaim(payment_id.clone()))
+        {
+            panic!("Payment already exists");
+        }
 
-        let fee_amount: i128 = env.storage().instance().get(&DataKey::FeeAmount).unwrap();
-        let expected_total_amount = payment_amount + fee_amount;
+        let fee_amount = Self::account_funded_payment(&env, payment_amount);
 
-        // Ensure the vault has been funded for this payment before accounting for it.
-        let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
-        let token_client = token::Client::new(&env, &usdc_token);
-        let vault_balance = token_client.balance(&env.current_contract_address());
+        let expiry_ledger = env.ledger().sequence().saturating_add(CLAIM_EXPIRY_LEDGERS);
+        let claim = PendingClaim {
+            recipient: user_wallet.clone(),
+            payment_amount,
+            fee_amount,
+            expiry_ledger,
+            claimed_amount: 0,
+        };
+        Self::store_pending_claim(&env, &payment_id, &claim);
 
-        let mut available_payments: i128 = env
-            .storage()
-            .instance()
-            .get(&DataKey::AvailablePayments)
-            .unwrap_or(0);
-        let mut total_payments: i128 = env
+        PaymentProcessedEvent {
+            user_wallet: user_wallet.clone(),
+            payment_id: payment_id.clone(),
+            payment_amount,
+            fee_amount,
+        }
+        .publish(&env);
+    }
+
+    /// Pull `payment_amount` plus fee from `user_wallet` through its token
+    /// allowance to the vault and book the payment in one call (operator only).
+    /// Unlike `process_payment`, funding is not inferred from the vault balance.
+    /// The wallet must authorize the call too, so an operator cannot spend an
+    /// allowance granted for recurring schedules; a `UserWallet` does so by
+    /// calling it from `deposit_to_vault`.
+    pub fn deposit_and_process(
+        env: Env,
+        caller: Address,
+        user_wallet: Address,
+        payment_amount: i128,
+        payment_id: BytesN<32>,
+    ) {
+        access_control::require_role(&env, &caller, access_control::OPERATOR_ROLE);
+        caller.require_auth();
+        user_wallet.require_auth();
+
+        let paused: bool = env
             .storage()
             .instance()
-            .get(&DataKey::TotalPayments)
-            .unwrap_or(0);
+            .get(&DataKey::Paused)
+            .unwrap_or(false);
+        if paused {
+            panic!("Contract is paused");
+        }
 
-        let available_fees_before: i128 = env
+        if payment_amount <= 0 {
+            panic!("Payment amount must be > 0");
+        }
+        if env
             .storage()
-            .instance()
-            .get(&DataKey::AvailableFees)
-            .unwrap_or(0);
-        let required_balance_after = available_payments
-            .checked_add(available_fees_before)
-            .and_then(|v| v.checked_add(expected_total_amount))
-            .expect("Amount overflow");
-        if vault_balance < required_balance_after {
-            panic!("Payment not funded");
+            .persistent()
+            .has(&DataKey::PendingClaim(payment_id.clone()))
+        {
+            panic!("Payment already exists");
         }
 
-        available_payments += payment_amount;
-        total_payments += payment_amount;
+        let fee_amount = Self::pull_and_book_payment(&env, &user_wallet, payment_amount);
 
-        env.storage()
-            .instance()
-            .set(&DataKey::AvailablePayments, &available_payments);
-        env.storage()
-            .instance()
-            .set(&DataKey::TotalPayments, &total_payments);
+        let expiry_ledger = env.ledger().sequence().saturating_add(CLAIM_EXPIRY_LEDGERS);
+        let claim = PendingClaim {
+            recipient: user_wallet.clone(),
+            payment_amount,
+            fee_amount,
+            expiry_ledger,
+            claimed_amount: 0,
+        };
+        Self::store_pending_claim(&env, &payment_id, &claim);
 
-        // Update fee tracking
-        let mut available_fees: i128 = available_fees_before;
-        let mut total_fees: i128 = env
+        PaymentProcessedEvent {
+            user_wallet,
+            payment_id,
+            payment_amount,
+            fee_amount,
+        }
+        .publish(&env);
+    }
+
+    /// Pull `payment_amount` plus fee from the calling `wallet` and book the
+    /// payment for `recipient` under `payment_id` in the same call. The wallet
+    /// must authorize the token transfer, so funding is bound to this payment.
+    /// Charges the fee quoted for `payment_id` while the quote is valid, otherwise
+    /// the current fee, and aborts if that is above `max_fee`.
+    pub fn process_wallet_payment(
+        env: Env,
+        wallet: Address,
+        recipient: Address,
+        payment_amount: i128,
+        payment_id: BytesN<32>,
+        max_fee: i128,
+    ) -> PaymentReceipt {
+        wallet.require_auth();
+
+        let paused: bool = env
             .storage()
             .instance()
-            .get(&DataKey::TotalFees)
-            .unwrap_or(0);
+            .get(&DataKey::Paused)
+            .unwrap_or(false);
+        if paused {
+            panic!("Contract is paused");
+        }
 
-        available_fees += fee_amount;
-        total_fees += fee_amount;
+        if payment_amount <= 0 {
+            panic!("Payment amount must be > 0");
+        }
+        if env
+            .storage()
+            .persistent()
+            .has(&DataKey::PendingClaim(payment_id.clone()))
+        {
+            panic!("Payment already exists");
+        }
 
+        let fee_amount = Self::get_payment_fee(env.clone(), wallet.clone(), payment_id.clone());
+        if fee_amount > max_fee {
+            panic!("Fee exceeds max_fee");
+        }
         env.storage()
-            .instance()
-            .set(&DataKey::AvailableFees, &available_fees);
-        env.storage()
-            .instance()
-            .set(&DataKey::TotalFees, &total_fees);
+            .temporary()
+            .remove(&DataKey::FeeQuote(wallet.clone(), payment_id.clone()));
+        let total_amount = payment_amount
+            .checked_add(fee_amount)
+            .expect("Amount overflow");
+
+        let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
+        let vault = env.current_contract_address();
+        token::Client::new(&env, &usdc_token).transfer(&wallet, &vault, &total_amount);
+
+        Self::book_payment(&env, payment_amount, fee_amount);
 
         let expiry_ledger = env.ledger().sequence().saturating_add(CLAIM_EXPIRY_LEDGERS);
         let claim = PendingClaim {
-            recipient: user_wallet.clone(),
+            recipient: recipient.clone(),
             payment_amount,
             fee_amount,
             expiry_ledger,
+            claimed_amount: 0,
         };
-        env.storage()
-            .instance()
-            .set(&DataKey::PendingClaim(payment_id.clone()), &claim);
+        Self::store_pending_claim(&env, &payment_id, &claim);
 
         PaymentProcessedEvent {
-            user_wallet: user_wallet.clone(),
+            user_wallet: wallet.clone(),
             payment_id: payment_id.clone(),
             payment_amount,
             fee_amount,
         }
         .publish(&env);
+
+        PaymentReceipt {
+            payment_id,
+            payer: wallet,
+            recipient,
+            payment_amount,
+            fee_amount,
+            total_amount,
+            expiry_ledger,
+        }
+    }
+
+    /// Process a streaming payout (operator only). The recipient can claim the
+    /// linearly vested portion between `start_ledger` and `end_ledger`, nothing
+    /// before `cliff_ledger`; the payer can cancel and recover the unvested rest.
+    pub fn process_vesting_payment(
+        env: Env,
+        caller: Address,
+        recipient: Address,
+        payment_amount: i128,
+        payment_id: BytesN<32>,
+        schedule: VestingSchedule,
+    ) {
+        access_control::require_role(&env, &caller, access_control::OPERATOR_ROLE);
+        caller.require_auth();
+
+        let paused: bool = env
+            .storage()
+            .instance()
+            .get(&DataKey::Paused)
+            .unwrap_or(false);
+        if paused {
+            panic!("Contract is paused");
+        }
+
+        if payment_amount <= 0 {
+            panic!("Payment amount must be > 0");
+        }
+        if schedule.end_ledger <= schedule.start_ledger {
+            panic!("End ledger must be after start ledger");
+        }
+        if let Some(cliff) = schedule.cliff_ledger {
+            if cliff < schedule.start_ledger || cliff > schedule.end_ledger {
+                panic!("Cliff must be within vesting period");
+            }
+        }
+        if env
+            .storage()
+            .persistent()
+            .has(&DataKey::PendingClaim(payment_id.clone()))
+        {
+            panic!("Payment already exists");
+        }
+
+        let fee_amount = Self::account_funded_payment(&env, payment_amount);
+
+        let claim = PendingClaim {
+            recipient: recipient.clone(),
+            payment_amount,
+            fee_amount,
+            expiry_ledger: schedule.end_ledger.saturating_add(CLAIM_EXPIRY_LEDGERS),
+            claimed_amount: 0,
+        };
+        Self::store_pending_claim(&env, &payment_id, &claim);
+        Self::set_persistent(
+            &env,
+            &DataKey::VestingSchedule(payment_id.clone()),
+            &schedule,
+        );
+
+        VestingPaymentCreatedEvent {
+            payer: schedule.payer,
+            recipient,
+            payment_id,
+            payment_amount,
+            fee_amount,
+            start_ledger: schedule.start_ledger,
+            end_ledger: schedule.end_ledger,
+            cliff_ledger: schedule.cliff_ledger,
+        }
+        .publish(&env);
     }
 
-    
-    /// Claim a pending payment (recipient only, within expiry window)
+    /// Claim a pending payment (recipient only, within expiry window).
+    /// For streaming payouts only the portion vested so far is released.
     pub fn claim(env: Env, caller: Address, payment_id: BytesN<32>) {
         caller.require_auth();
 
@@ -271,9 +571,9 @@ impl Vault {
         }
 
         // Load pending claim
-        let claim: PendingClaim = env
+        let mut claim: PendingClaim = env
             .storage()
-            .instance()
+            .persistent()
             .get(&DataKey::PendingClaim(payment_id.clone()))
             .unwrap_or_else(|| panic!("Pending claim not found"));
 
@@ -288,74 +588,91 @@ impl Vault {
             panic!("Claim window has expired");
         }
 
-        // Update accounting — reduce available and total for both payments and fees
-        let mut available_payments: i128 = env
-            .storage()
-            .instance()
-            .get(&DataKey::AvailablePayments)
-            .unwrap_or(0);
-        let mut total_payments: i128 = env
-            .storage()
-            .instance()
-            .get(&DataKey::TotalPayments)
-            .unwrap_or(0);
-        let mut available_fees: i128 = env
-            .storage()
-            .instance()
-            .get(&DataKey::AvailableFees)
-            .unwrap_or(0);
-        let mut total_fees: i128 = env
-            .storage()
-            .instance()
-            .get(&DataKey::TotalFees)
-            .unwrap_or(0);
+        let releasable = Self::vested_amount(&env, &payment_id, &claim) - claim.claimed_amount;
+        if releasable <= 0 {
+            panic!("Nothing vested to claim");
+        }
+        claim.claimed_amount += releasable;
 
-        available_payments = available_payments
-            .checked_sub(claim.payment_amount)
-            .unwrap_or_else(|| panic!("Available payments underflow"));
-        total_payments = total_payments
-            .checked_sub(claim.payment_amount)
-            .unwrap_or_else(|| panic!("Total payments underflow"));
-        available_fees = available_fees
-            .checked_sub(claim.fee_amount)
-            .unwrap_or_else(|| panic!("Available fees underflow"));
-        total_fees = total_fees
-            .checked_sub(claim.fee_amount)
-            .unwrap_or_else(|| panic!("Total fees underflow"));
+        // The fee leaves the books together with the last released tranche.
+        let fully_claimed = claim.claimed_amount == claim.payment_amount;
+        let fee_released = if fully_claimed { claim.fee_amount } else { 0 };
+        Self::release_from_accounting(&env, releasable, fee_released);
 
-        env.storage()
-            .instance()
-            .set(&DataKey::AvailablePayments, &available_payments);
-        env.storage()
-            .instance()
-            .set(&DataKey::TotalPayments, &total_payments);
-        env.storage()
-            .instance()
-            .set(&DataKey::AvailableFees, &available_fees);
-        env.storage()
-            .instance()
-            .set(&DataKey::TotalFees, &total_fees);
-
-        // Transfer payment amount to recipient
+        // Transfer released amount to recipient
         let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
         let token_client = token::Client::new(&env, &usdc_token);
-        token_client.transfer(
-            &env.current_contract_address(),
-            &caller,
-            &claim.payment_amount,
-        );
+        token_client.transfer(&env.current_contract_address(), &caller, &releasable);
 
-        // Remove pending claim from storage
-        env.storage()
-            .instance()
-            .remove(&DataKey::PendingClaim(payment_id.clone()));
+        if fully_claimed {
+            Self::remove_pending_claim(&env, &payment_id);
+        } else {
+            Self::set_persistent(&env, &DataKey::PendingClaim(payment_id.clone()), &claim);
+            Self::adjust_open_claims(&env, -releasable);
+        }
 
         // Emit event
         PaymentClaimedEvent {
             recipient: caller,
             payment_id,
-            payment_amount: claim.payment_amount,
-            fee_amount: claim.fee_amount,
+            payment_amount: releasable,
+            fee_amount: fee_released,
+        }
+        .publish(&env);
+    }
+
+    /// Cancel a streaming payout (payer or admin). The vested but unclaimed
+    /// portion goes to the recipient and the unvested remainder back to the payer.
+    /// The fee stays booked as collected.
+    pub fn cancel_vesting_payment(env: Env, caller: Address, payment_id: BytesN<32>) {
+        let claim: PendingClaim = env
+            .storage()
+            .persistent()
+            .get(&DataKey::PendingClaim(payment_id.clone()))
+            .unwrap_or_else(|| panic!("Pending claim not found"));
+
+        let vesting: VestingSchedule = env
+            .storage()
+            .persistent()
+            .get(&DataKey::VestingSchedule(payment_id.clone()))
+            .unwrap_or_else(|| panic!("Not a vesting payment"));
+
+        if caller != vesting.payer
+            && !access_control::has_role(&env, &caller, access_control::ADMIN_ROLE)
+        {
+            panic!("Only payer or admin");
+        }
+        caller.require_auth();
+
+        let vested = Self::vested_amount(&env, &payment_id, &claim);
+        let recipient_amount = vested - claim.claimed_amount;
+        let refund_amount = claim.payment_amount - vested;
+
+        Self::release_from_accounting(&env, recipient_amount + refund_amount, 0);
+        Self::remove_pending_claim(&env, &payment_id);
+
+        let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
+        let token_client = token::Client::new(&env, &usdc_token);
+        if recipient_amount > 0 {
+            token_client.transfer(
+                &env.current_contract_address(),
+                &claim.recipient,
+                &recipient_amount,
+            );
+        }
+        if refund_amount > 0 {
+            token_client.transfer(
+                &env.current_contract_address(),
+                &vesting.payer,
+                &refund_amount,
+            );
+        }
+
+        VestingPaymentCancelledEvent {
+            payment_id,
+            recipient_amount,
+            refund_amount,
+            cancelled_by: caller,
         }
         .publish(&env);
     }
@@ -368,16 +685,16 @@ impl Vault {
         payment_id: BytesN<32>,
         force: bool,
     ) {
-        if !access_control::has_role(&env, &caller, access_control::ADMIN_ROLE)
-            && !access_control::has_role(&env, &caller, access_control::OPERATOR_ROLE)
-        {
-            panic!("Missing required role");
-        }
+        access_control::require_any_role(
+            &env,
+            &caller,
+            &[access_control::ADMIN_ROLE, access_control::OPERATOR_ROLE],
+        );
         caller.require_auth();
 
         let claim: PendingClaim = env
             .storage()
-            .instance()
+            .persistent()
             .get(&DataKey::PendingClaim(payment_id.clone()))
             .unwrap_or_else(|| panic!("Pending claim not found"));
 
@@ -388,65 +705,179 @@ impl Vault {
             }
         }
 
-        let mut available_payments: i128 = env
+        let unclaimed_amount = claim.payment_amount - claim.claimed_amount;
+        Self::release_from_accounting(&env, unclaimed_amount, claim.fee_amount);
+        Self::remove_pending_claim(&env, &payment_id);
+
+        PaymentCancelledEvent {
+            payment_id: payment_id.clone(),
+            payment_amount: unclaimed_amount,
+            fee_amount: claim.fee_amount,
+            cancelled_by: caller,
+            force,
+        }
+        .publish(&env);
+    }
+
+    /// Create a recurring payment schedule (payer auth). The first cycle is due
+    /// immediately; the payer must grant the vault a token allowance covering
+    /// `amount` plus the vault fee for every cycle it expects to run.
+    pub fn create_schedule(
+        env: Env,
+        payer: Address,
+        recipient: Address,
+        amount: i128,
+        interval_ledgers: u32,
+        max_cycles: u32,
+    ) -> u64 {
+        payer.require_auth();
+
+        let paused: bool = env
             .storage()
             .instance()
-            .get(&DataKey::AvailablePayments)
-            .unwrap_or(0);
-        let mut total_payments: i128 = env
+            .get(&DataKey::Paused)
+            .unwrap_or(false);
+        if paused {
+            panic!("Contract is paused");
+        }
+
+        if amount <= 0 {
+            panic!("Payment amount must be > 0");
+        }
+        if interval_ledgers == 0 {
+            panic!("Interval must be > 0");
+        }
+        if max_cycles == 0 {
+            panic!("Max cycles must be > 0");
+        }
+
+        let schedule_id: u64 = env
             .storage()
             .instance()
-            .get(&DataKey::TotalPayments)
+            .get(&DataKey::NextScheduleId)
             .unwrap_or(0);
-        let mut available_fees: i128 = env
-            .storage()
+        env.storage()
             .instance()
-            .get(&DataKey::AvailableFees)
-            .unwrap_or(0);
-        let mut total_fees: i128 = env
+            .set(&DataKey::NextScheduleId, &(schedule_id + 1));
+
+        let schedule = RecurringSchedule {
+            payer: payer.clone(),
+            recipient: recipient.clone(),
+            amount,
+            interval_ledgers,
+            max_cycles,
+            cycles_executed: 0,
+            next_ledger: env.ledger().sequence(),
+            status: ScheduleStatus::Active,
+        };
+        Self::save_schedule(&env, schedule_id, &schedule);
+
+        ScheduleCreatedEvent {
+            schedule_id,
+            payer,
+            recipient,
+            amount,
+            interval_ledgers,
+            max_cycles,
+        }
+        .publish(&env);
+
+        schedule_id
+    }
+
+    /// Run the current cycle of a schedule if it is due (anyone may call).
+    /// Pulls `amount` plus fee from the payer's allowance, books it as a pending
+    /// claim for the recipient and returns the derived payment id. Periods
+    /// missed entirely are skipped, so at most one cycle is paid per interval.
+    pub fn execute_due(env: Env, schedule_id: u64) -> BytesN<32> {
+        let paused: bool = env
             .storage()
             .instance()
-            .get(&DataKey::TotalFees)
-            .unwrap_or(0);
+            .get(&DataKey::Paused)
+            .unwrap_or(false);
+        if paused {
+            panic!("Contract is paused");
+        }
 
-        available_payments = available_payments
-            .checked_sub(claim.payment_amount)
-            .unwrap_or_else(|| panic!("Available payments underflow"));
-        total_payments = total_payments
-            .checked_sub(claim.payment_amount)
-            .unwrap_or_else(|| panic!("Total payments underflow"));
-        available_fees = available_fees
-            .checked_sub(claim.fee_amount)
-            .unwrap_or_else(|| panic!("Available fees underflow"));
-        total_fees = total_fees
-            .checked_sub(claim.fee_amount)
-            .unwrap_or_else(|| panic!("Total fees underflow"));
+        let mut schedule = Self::load_schedule(&env, schedule_id);
 
-        env.storage()
-            .instance()
-            .set(&DataKey::AvailablePayments, &available_payments);
-        env.storage()
-            .instance()
-            .set(&DataKey::TotalPayments, &total_payments);
-        env.storage()
-            .instance()
-            .set(&DataKey::AvailableFees, &available_fees);
-        env.storage()
-            .instance()
-            .set(&DataKey::TotalFees, &total_fees);
+        if schedule.status != ScheduleStatus::Active {
+            panic!("Schedule is not active");
+        }
+        let current_ledger = env.ledger().sequence();
+        if current_ledger < schedule.next_ledger {
+            panic!("Schedule is not due");
+        }
 
-        env.storage()
-            .instance()
-            .remove(&DataKey::PendingClaim(payment_id.clone()));
+        let cycle = schedule.cycles_executed;
+        let mut payment_seed = Bytes::from_slice(&env, b"RECURRING");
+        payment_seed.extend_from_array(&schedule_id.to_be_bytes());
+        payment_seed.extend_from_array(&cycle.to_be_bytes());
+        let payment_id: BytesN<32> = env.crypto().sha256(&payment_seed).into();
+        if env
+            .storage()
+            .persistent()
+            .has(&DataKey::PendingClaim(payment_id.clone()))
+        {
+            panic!("Payment already exists");
+        }
 
-        PaymentCancelledEvent {
+        let fee_amount = Self::pull_and_book_payment(&env, &schedule.payer, schedule.amount);
+
+        let claim = PendingClaim {
+            recipient: schedule.recipient.clone(),
+            payment_amount: schedule.amount,
+            fee_amount,
+            expiry_ledger: env.ledger().sequence().saturating_add(CLAIM_EXPIRY_LEDGERS),
+            claimed_amount: 0,
+        };
+        Self::store_pending_claim(&env, &payment_id, &claim);
+
+        schedule.cycles_executed += 1;
+        let missed_periods = (current_ledger - schedule.next_ledger) / schedule.interval_ledgers;
+        schedule.next_ledger = schedule.next_ledger.saturating_add(
+            schedule
+                .interval_ledgers
+                .saturating_mul(missed_periods.saturating_add(1)),
+        );
+        if schedule.cycles_executed >= schedule.max_cycles {
+            schedule.status = ScheduleStatus::Completed;
+        }
+        Self::save_schedule(&env, schedule_id, &schedule);
+
+        PaymentProcessedEvent {
+            user_wallet: schedule.payer.clone(),
             payment_id: payment_id.clone(),
-            payment_amount: claim.payment_amount,
-            fee_amount: claim.fee_amount,
-            cancelled_by: caller,
-            force,
+            payment_amount: schedule.amount,
+            fee_amount,
+        }
+        .publish(&env);
+
+        ScheduleExecutedEvent {
+            schedule_id,
+            payment_id: payment_id.clone(),
+            cycle,
+            amount: schedule.amount,
+            fee_amount,
         }
         .publish(&env);
+
+        payment_id
+    }
+
+    /// Pause an active schedule (payer only)
+    pub fn pause_schedule(env: Env, schedule_id: u64) {
+        Self::set_schedule_status(&env, schedule_id, ScheduleStatus::Paused);
+    }
+
+    /// Resume a paused schedule (payer only)
+    pub fn resume_schedule(env: Env, schedule_id: u64) {
+        Self::set_schedule_status(&env, schedule_id, ScheduleStatus::Active);
+    }
+
+    /// Cancel a schedule for good (payer only)
+    pub fn cancel_schedule(env: Env, schedule_id: u64) {
+        Self::set_schedule_status(&env, schedule_id, ScheduleStatus::Cancelled);
     }
 
     /// Refund payment (admin only)
@@ -552,6 +983,56 @@ impl Vault {
         .publish(&env);
     }
 
+    /// Move the token balance held above all accounted funds, e.g. dust,
+    /// donations or fees released by claims (admin only)
+    pub fn recover_surplus(env: Env, caller: Address, to: Address) -> i128 {
+        access_control::require_role(&env, &caller, access_control::ADMIN_ROLE);
+        caller.require_auth();
+
+        Self::check_accounting_invariants(env.clone());
+
+        let report = Self::get_accounting_report(env.clone());
+        if report.surplus <= 0 {
+            panic!("No surplus to recover");
+        }
+
+        let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
+        let token_client = token::Client::new(&env, &usdc_token);
+        token_client.transfer(&env.current_contract_address(), &to, &report.surplus);
+
+        SurplusRecoveredEvent {
+            to,
+            amount: report.surplus,
+        }
+        .publish(&env);
+
+        report.surplus
+    }
+
+    /// Recover tokens sent to the vault by mistake (admin only). For the
+    /// settlement asset only the surplus above accounted funds can be moved.
+    pub fn rescue_token(env: Env, caller: Address, token: Address, to: Address, amount: i128) {
+        access_control::require_role(&env, &caller, access_control::ADMIN_ROLE);
+        caller.require_auth();
+
+        if amount <= 0 {
+            panic!("Amount must be > 0");
+        }
+
+        let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
+        if token == usdc_token {
+            let report = Self::get_accounting_report(env.clone());
+            if amount > report.surplus {
+                panic!("Amount exceeds unaccounted balance");
+            }
+        }
+
+        let token_client = token::Client::new(&env, &token);
+        token_client.transfer(&env.current_contract_address(), &to, &amount);
+
+        TokenRescuedEvent { token, to, amount }.publish(&env);
+    }
+
     /// Update fee (admin only)
     pub fn set_fee(env: Env, caller: Address, new_fee: i128) {
         access_control::require_role(&env, &caller, access_control::ADMIN_ROLE);
@@ -611,14 +1092,16 @@ impl Vault {
         access_control::require_role(&env, &caller, access_control::ADMIN_ROLE);
         caller.require_auth();
 
-        access_control::grant_role(&env, account, role);
+        Self::grant(&env, account, role);
     }
 
     pub fn revoke_role(env: Env, caller: Address, account: Address, role: Symbol) {
         access_control::require_role(&env, &caller, access_control::ADMIN_ROLE);
         caller.require_auth();
 
-        access_control::revoke_role(&env, account, role);
+        if access_control::revoke_role(&env, account.clone(), role.clone()) {
+            RoleRevokedEvent { account, role }.publish(&env);
+        }
     }
 
     pub fn has_role(env: Env, account: Address, role: Symbol) -> bool {
@@ -626,13 +1109,63 @@ impl Vault {
     }
 
     pub fn get_admin(env: Env) -> Address {
-        env.storage().instance().get(&DataKey::Admin).unwrap()
+        access_control::get_primary(&env, access_control::ADMIN_ROLE).unwrap()
     }
 
     pub fn get_fee_amount(env: Env) -> i128 {
         env.storage().instance().get(&DataKey::FeeAmount).unwrap()
     }
 
+    /// Pin the current fee for `payer`'s wallet payment `payment_id`. Only the
+    /// payer or an operator may pin; an unexpired quote is returned as is.
+    pub fn quote_fee(
+        env: Env,
+        caller: Address,
+        payer: Address,
+        payment_id: BytesN<32>,
+    ) -> FeeQuote {
+        if caller != payer {
+            access_control::require_role(&env, &caller, access_control::OPERATOR_ROLE);
+        }
+        caller.require_auth();
+
+        let key = DataKey::FeeQuote(payer, payment_id);
+        let existing: Option<FeeQuote> = env.storage().temporary().get(&key);
+        if let Some(quote) = existing {
+            if env.ledger().sequence() <= quote.valid_until_ledger {
+                return quote;
+            }
+        }
+
+        let quote = FeeQuote {
+            fee_amount: Self::get_fee_amount(env.clone()),
+            valid_until_ledger: env
+                .ledger()
+                .sequence()
+                .saturating_add(FEE_QUOTE_VALIDITY_LEDGERS),
+        };
+        env.storage().temporary().set(&key, &quote);
+        env.storage().temporary().extend_ttl(
+            &key,
+            FEE_QUOTE_VALIDITY_LEDGERS,
+            FEE_QUOTE_VALIDITY_LEDGERS,
+        );
+        quote
+    }
+
+    /// Fee `process_wallet_payment` charges `payer` for `payment_id`: its
+    /// unexpired quote if there is one, otherwise the current fee.
+    pub fn get_payment_fee(env: Env, payer: Address, payment_id: BytesN<32>) -> i128 {
+        let quote: Option<FeeQuote> = env
+            .storage()
+            .temporary()
+            .get(&DataKey::FeeQuote(payer, payment_id));
+        match quote {
+            Some(q) if env.ledger().sequence() <= q.valid_until_ledger => q.fee_amount,
+            _ => Self::get_fee_amount(env),
+        }
+    }
+
     pub fn get_min_deposit(env: Env) -> i128 {
         env.storage().instance().get(&DataKey::MinDeposit).unwrap()
     }
@@ -676,35 +1209,87 @@ impl Vault {
             .unwrap_or(0);
         let required_balance = available_payments + available_fees;
 
-        vault_balance >= required_balance
+        vault_balance >= required_balance && Self::open_claims_total(&env) <= available_payments
+    }
+
+    pub fn get_accounting_report(env: Env) -> AccountingReport {
+        let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
+        let token_client = token::Client::new(&env, &usdc_token);
+        let token_balance = token_client.balance(&env.current_contract_address());
+
+        let available_payments: i128 = env
+            .storage()
+            .instance()
+            .get(&DataKey::AvailablePayments)
+            .unwrap_or(0);
+        let total_payments: i128 = env
+            .storage()
+            .instance()
+            .get(&DataKey::TotalPayments)
+            .unwrap_or(0);
+        let available_fees: i128 = env
+            .storage()
+            .instance()
+            .get(&DataKey::AvailableFees)
+            .unwrap_or(0);
+        let total_fees: i128 = env
+            .storage()
+            .instance()
+            .get(&DataKey::TotalFees)
+            .unwrap_or(0);
+
+        AccountingReport {
+            token_balance,
+            available_payments,
+            total_payments,
+            available_fees,
+            total_fees,
+            open_claims: Self::open_claims_total(&env),
+            surplus: token_balance - available_payments - available_fees,
+        }
+    }
+
+    /// Panic if the books are inconsistent: open claims must be backed by
+    /// `AvailablePayments`, and the token balance must cover all accounted funds.
+    pub fn check_accounting_invariants(env: Env) {
+        let report = Self::get_accounting_report(env);
+        if report.open_claims > report.available_payments {
+            panic!("Open claims exceed available payments");
+        }
+        if report.surplus < 0 {
+            panic!("Vault balance below accounted funds");
+        }
     }
 
     pub fn get_pending_claim(env: Env, payment_id: BytesN<32>) -> Option<PendingClaim> {
         env.storage()
-            .instance()
+            .persistent()
             .get(&DataKey::PendingClaim(payment_id))
     }
 
-    pub fn get_all_pending_claims(env: Env) -> Vec<BytesN<32>> {
-        env.storage()
-            .instance()
-            .get(&DataKey::AllPendingClaims)
-            .unwrap_or_else(|| Vec::new(&env))
+    /// List open pending claims, at most 100 per page. Removing a claim moves
+    /// the last one into its position, so the order is not stable.
+    pub fn get_all_pending_claims(env: Env, offset: u32, limit: u32) -> Vec<BytesN<32>> {
+        Self::list_index(
+            &env,
+            DataKey::PendingClaimCount,
+            DataKey::PendingClaimAt,
+            offset,
+            limit,
+        )
     }
 
     pub fn get_pending_claims_count(env: Env) -> u32 {
-        let all_claims: Vec<BytesN<32>> = env
-            .storage()
-            .instance()
-            .get(&DataKey::AllPendingClaims)
-            .unwrap_or_else(|| Vec::new(&env));
-        all_claims.len()
+        env.storage()
+            .persistent()
+            .get(&DataKey::PendingClaimCount)
+            .unwrap_or(0)
     }
 
     pub fn is_claim_expired(env: Env, payment_id: BytesN<32>) -> bool {
         let claim: Option<PendingClaim> = env
             .storage()
-            .instance()
+            .persistent()
             .get(&DataKey::PendingClaim(payment_id));
         
         match claim {
@@ -716,7 +1301,420 @@ impl Vault {
         }
     }
 
-    pub fn get_recipient_pending_claims(env: Env, _recipient: Address) -> Vec<BytesN<32>> {
-        Vec::new(&env)
+    /// List the recipient's open pending claims, at most 100 per page
+    pub fn get_recipient_pending_claims(
+        env: Env,
+        recipient: Address,
+        offset: u32,
+        limit: u32,
+    ) -> Vec<BytesN<32>> {
+        let count_key = DataKey::RecipientClaimCount(recipient.clone());
+        let at = |index| DataKey::RecipientClaimAt(recipient.clone(), index);
+        Self::list_index(&env, count_key, at, offset, limit)
+    }
+
+    /// Amount the recipient could claim right now (vested minus claimed to date).
+    pub fn get_claimable_amount(env: Env, payment_id: BytesN<32>) -> i128 {
+        let claim: Option<PendingClaim> = env
+            .storage()
+            .persistent()
+            .get(&DataKey::PendingClaim(payment_id.clone()));
+
+        match claim {
+            Some(c) => Self::vested_amount(&env, &payment_id, &c) - c.claimed_amount,
+            None => 0,
+        }
+    }
+
+    pub fn get_vesting_schedule(env: Env, payment_id: BytesN<32>) -> Option<VestingSchedule> {
+        env.storage()
+            .persistent()
+            .get(&DataKey::VestingSchedule(payment_id))
+    }
+
+    /// Active or paused schedule; finished schedules are removed.
+    pub fn get_schedule(env: Env, schedule_id: u64) -> Option<RecurringSchedule> {
+        env.storage()
+            .persistent()
+            .get(&DataKey::Schedule(schedule_id))
+    }
+
+    // --- Internal Helpers ---
+
+    fn set_schedule_status(env: &Env, schedule_id: u64, status: ScheduleStatus) {
+        let mut schedule = Self::load_schedule(env, schedule_id);
+
+        schedule.payer.require_auth();
+
+        let allowed = match status {
+            ScheduleStatus::Paused => schedule.status == ScheduleStatus::Active,
+            ScheduleStatus::Active => schedule.status == ScheduleStatus::Paused,
+            ScheduleStatus::Cancelled => {
+                schedule.status == ScheduleStatus::Active
+                    || schedule.status == ScheduleStatus::Paused
+            }
+            ScheduleStatus::Completed => false,
+        };
+        if !allowed {
+            panic!("Invalid schedule status transition");
+        }
+
+        schedule.status = status.clone();
+        Self::save_schedule(env, schedule_id, &schedule);
+
+        ScheduleStatusUpdatedEvent {
+            schedule_id,
+            status,
+        }
+        .publish(env);
+    }
+
+    fn load_schedule(env: &Env, schedule_id: u64) -> RecurringSchedule {
+        env.storage()
+            .persistent()
+            .get(&DataKey::Schedule(schedule_id))
+            .unwrap_or_else(|| panic!("Schedule not found"))
+    }
+
+    /// Persist a schedule and extend its TTL, or drop it once it is finished.
+    fn save_schedule(env: &Env, schedule_id: u64, schedule: &RecurringSchedule) {
+        let key = DataKey::Schedule(schedule_id);
+        let storage = env.storage().persistent();
+        match schedule.status {
+            ScheduleStatus::Completed | ScheduleStatus::Cancelled => storage.remove(&key),
+            ScheduleStatus::Active | ScheduleStatus::Paused => {
+                storage.set(&key, schedule);
+                storage.extend_ttl(&key, SCHEDULE_TTL_LEDGERS, SCHEDULE_TTL_LEDGERS);
+            }
+        }
+    }
+
+    /// Check the vault holds enough tokens for this payment plus everything
+    /// already accounted, then book the payment and current fee. Returns the fee.
+    fn account_funded_payment(env: &Env, payment_amount: i128) -> i128 {
+        let fee_amount: i128 = env.storage().instance().get(&DataKey::FeeAmount).unwrap();
+        let expected_total_amount = payment_amount + fee_amount;
+
+        // Ensure the vault has been funded for this payment before accounting for it.
+        let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
+        let token_client = token::Client::new(env, &usdc_token);
+        let vault_balance = token_client.balance(&env.current_contract_address());
+
+        let available_payments: i128 = env
+            .storage()
+            .instance()
+            .get(&DataKey::AvailablePayments)
+            .unwrap_or(0);
+        let available_fees: i128 = env
+            .storage()
+            .instance()
+            .get(&DataKey::AvailableFees)
+            .unwrap_or(0);
+        let required_balance_after = available_payments
+            .checked_add(available_fees)
+            .and_then(|v| v.checked_add(expected_total_amount))
+            .expect("Amount overflow");
+        if vault_balance < required_balance_after {
+            panic!("Payment not funded");
+        }
+
+        Self::book_payment(env, payment_amount, fee_amount);
+        fee_amount
+    }
+
+    /// Pull this payment plus the current fee from `payer` through the vault's
+    /// token allowance and book both in the same call. Returns the fee.
+    fn pull_and_book_payment(env: &Env, payer: &Address, payment_amount: i128) -> i128 {
+        let fee_amount: i128 = env.storage().instance().get(&DataKey::FeeAmount).unwrap();
+        let total_amount = payment_amount
+            .checked_add(fee_amount)
+            .expect("Amount overflow");
+
+        let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
+        token_helpers::transfer_token_from(
+            env,
+            &usdc_token,
+            &env.current_contract_address(),
+            payer,
+            &env.current_contract_address(),
+            total_amount,
+        );
+
+        Self::book_payment(env, payment_amount, fee_amount);
+        fee_amount
     }
-}
\ No newline at end of file
+
+    /// Increase available and total for both payments and fees.
+    fn book_payment(env: &Env, payment_amount: i128, fee_amount: i128) {
+        let mut available_payments: i128 = env
+            .storage()
+            .instance()
+            .get(&DataKey::AvailablePayments)
+            .unwrap_or(0);
+        let mut total_payments: i128 = env
+            .storage()
+            .instance()
+            .get(&DataKey::TotalPayments)
+            .unwrap_or(0);
+
+        available_payments += payment_amount;
+        total_payments += payment_amount;
+
+        env.storage()
+            .instance()
+            .set(&DataKey::AvailablePayments, &available_payments);
+        env.storage()
+            .instance()
+            .set(&DataKey::TotalPayments, &total_payments);
+
+        // Update fee tracking
+        let mut available_fees: i128 = env
+            .storage()
+            .instance()
+            .get(&DataKey::AvailableFees)
+            .unwrap_or(0);
+        let mut total_fees: i128 = env
+            .storage()
+            .instance()
+            .get(&DataKey::TotalFees)
+            .unwrap_or(0);
+
+        available_fees += fee_amount;
+        total_fees += fee_amount;
+
+        env.storage()
+            .instance()
+            .set(&DataKey::AvailableFees, &available_fees);
+        env.storage()
+            .instance()
+            .set(&DataKey::TotalFees, &total_fees);
+    }
+
+    /// Reduce available and total for both payments and fees.
+    fn release_from_accounting(env: &Env, payment_amount: i128, fee_amount: i128) {
+        let mut available_payments: i128 = env
+            .storage()
+            .instance()
+            .get(&DataKey::AvailablePayments)
+            .unwrap_or(0);
+        let mut total_payments: i128 = env
+            .storage()
+            .instance()
+            .get(&DataKey::TotalPayments)
+            .unwrap_or(0);
+        let mut available_fees: i128 = env
+            .storage()
+            .instance()
+            .get(&DataKey::AvailableFees)
+            .unwrap_or(0);
+        let mut total_fees: i128 = env
+            .storage()
+            .instance()
+            .get(&DataKey::TotalFees)
+            .unwrap_or(0);
+
+        available_payments = available_payments
+            .checked_sub(payment_amount)
+            .unwrap_or_else(|| panic!("Available payments underflow"));
+        total_payments = total_payments
+            .checked_sub(payment_amount)
+            .unwrap_or_else(|| panic!("Total payments underflow"));
+        available_fees = available_fees
+            .checked_sub(fee_amount)
+            .unwrap_or_else(|| panic!("Available fees underflow"));
+        total_fees = total_fees
+            .checked_sub(fee_amount)
+            .unwrap_or_else(|| panic!("Total fees underflow"));
+
+        env.storage()
+            .instance()
+            .set(&DataKey::AvailablePayments, &available_payments);
+        env.storage()
+            .instance()
+            .set(&DataKey::TotalPayments, &total_payments);
+        env.storage()
+            .instance()
+            .set(&DataKey::AvailableFees, &available_fees);
+        env.storage()
+            .instance()
+            .set(&DataKey::TotalFees, &total_fees);
+    }
+
+    fn store_pending_claim(env: &Env, payment_id: &BytesN<32>, claim: &PendingClaim) {
+        Self::set_persistent(env, &DataKey::PendingClaim(payment_id.clone()), claim);
+        Self::adjust_open_claims(env, claim.payment_amount - claim.claimed_amount);
+
+        Self::index_push(
+            env,
+            DataKey::PendingClaimCount,
+            DataKey::PendingClaimAt,
+            DataKey::PendingClaimPos,
+            payment_id,
+        );
+        let recipient = claim.recipient.clone();
+        Self::index_push(
+            env,
+            DataKey::RecipientClaimCount(recipient.clone()),
+            |index| DataKey::RecipientClaimAt(recipient.clone(), index),
+            DataKey::RecipientClaimPos,
+            payment_id,
+        );
+    }
+
+    fn remove_pending_claim(env: &Env, payment_id: &BytesN<32>) {
+        let claim: Option<PendingClaim> = env
+            .storage()
+            .persistent()
+            .get(&DataKey::PendingClaim(payment_id.clone()));
+        if let Some(c) = claim {
+            Self::adjust_open_claims(env, -(c.payment_amount - c.claimed_amount));
+
+            let recipient = c.recipient;
+            Self::index_remove(
+                env,
+                DataKey::RecipientClaimCount(recipient.clone()),
+                |index| DataKey::RecipientClaimAt(recipient.clone(), index),
+                DataKey::RecipientClaimPos,
+                payment_id,
+            );
+            Self::index_remove(
+                env,
+                DataKey::PendingClaimCount,
+                DataKey::PendingClaimAt,
+                DataKey::PendingClaimPos,
+                payment_id,
+            );
+        }
+
+        env.storage()
+            .persistent()
+            .remove(&DataKey::PendingClaim(payment_id.clone()));
+        env.storage()
+            .persistent()
+            .remove(&DataKey::VestingSchedule(payment_id.clone()));
+    }
+
+    /// Append `payment_id` to the index counted under `count_key`, remembering
+    /// its position so it can be removed without scanning.
+    fn index_push(
+        env: &Env,
+        count_key: DataKey,
+        at: impl Fn(u32) -> DataKey,
+        pos: impl Fn(BytesN<32>) -> DataKey,
+        payment_id: &BytesN<32>,
+    ) {
+        let count: u32 = env.storage().persistent().get(&count_key).unwrap_or(0);
+        Self::set_persistent(env, &at(count), payment_id);
+        Self::set_persistent(env, &pos(payment_id.clone()), &count);
+        Self::set_persistent(env, &count_key, &(count + 1));
+    }
+
+    /// Remove `payment_id` from the index by moving the last entry into its slot.
+    fn index_remove(
+        env: &Env,
+        count_key: DataKey,
+        at: impl Fn(u32) -> DataKey,
+        pos: impl Fn(BytesN<32>) -> DataKey,
+        payment_id: &BytesN<32>,
+    ) {
+        let storage = env.storage().persistent();
+        let index: u32 = match storage.get(&pos(payment_id.clone())) {
+            Some(index) => index,
+            None => return,
+        };
+        let last = storage.get::<_, u32>(&count_key).unwrap() - 1;
+        if index != last {
+            let moved: BytesN<32> = storage.get(&at(last)).unwrap();
+            Self::set_persistent(env, &at(index), &moved);
+            Self::set_persistent(env, &pos(moved), &index);
+        }
+        storage.remove(&at(last));
+        storage.remove(&pos(payment_id.clone()));
+        if last == 0 {
+            storage.remove(&count_key);
+        } else {
+            Self::set_persistent(env, &count_key, &last);
+        }
+    }
+
+    /// Page through an index written by `index_push`
+    fn list_index(
+        env: &Env,
+        count_key: DataKey,
+        at: impl Fn(u32) -> DataKey,
+        offset: u32,
+        limit: u32,
+    ) -> Vec<BytesN<32>> {
+        let storage = env.storage().persistent();
+        let total: u32 = storage.get(&count_key).unwrap_or(0);
+        let end = offset.saturating_add(limit.min(MAX_PAGE_SIZE)).min(total);
+
+        let mut ids = Vec::new(env);
+        for index in offset..end {
+            ids.push_back(storage.get(&at(index)).unwrap());
+        }
+        ids
+    }
+
+    /// Write a persistent entry and extend its TTL
+    fn set_persistent<V: IntoVal<Env, Val>>(env: &Env, key: &DataKey, value: &V) {
+        let storage = env.storage().persistent();
+        storage.set(key, value);
+        storage.extend_ttl(key, CLAIM_TTL_LEDGERS, CLAIM_TTL_LEDGERS);
+    }
+
+    /// Sum of the unclaimed amounts of every open pending claim, kept as a
+    /// running total so reads stay constant-cost.
+    fn open_claims_total(env: &Env) -> i128 {
+        env.storage()
+            .instance()
+            .get(&DataKey::OpenClaimsTotal)
+            .unwrap_or(0)
+    }
+
+    fn adjust_open_claims(env: &Env, delta: i128) {
+        let total = Self::open_claims_total(env)
+            .checked_add(delta)
+            .expect("Amount overflow");
+        env.storage()
+            .instance()
+            .set(&DataKey::OpenClaimsTotal, &total);
+    }
+
+    /// Portion of the claim released so far. Lump-sum claims vest immediately.
+    fn vested_amount(env: &Env, payment_id: &BytesN<32>, claim: &PendingClaim) -> i128 {
+        let vesting: VestingSchedule = match env
+            .storage()
+            .persistent()
+            .get(&DataKey::VestingSchedule(payment_id.clone()))
+        {
+            Some(v) => v,
+            None => return claim.payment_amount,
+        };
+
+        let current_ledger = env.ledger().sequence();
+        if current_ledger < vesting.cliff_ledger.unwrap_or(vesting.start_ledger)
+            || current_ledger <= vesting.start_ledger
+        {
+            return 0;
+        }
+        if current_ledger >= vesting.end_ledger {
+            return claim.payment_amount;
+        }
+
+        let elapsed = (current_ledger - vesting.start_ledger) as i128;
+        let duration = (vesting.end_ledger - vesting.start_ledger) as i128;
+        claim
+            .payment_amount
+            .checked_mul(elapsed)
+            .expect("Amount overflow")
+            / duration
+    }
+
+    /// Grant `role` to `account`, emitting an event if it was newly granted
+    fn grant(env: &Env, account: Address, role: Symbol) {
+        if access_control::grant_role(env, account.clone(), role.clone()) {
+            RoleGrantedEvent { account, role }.publish(env);
+        }
+    }
+}
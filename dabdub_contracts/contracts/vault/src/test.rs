@@ -1,6 +1,9 @@
 #![cfg(test)]
-use crate::{access_control, Vault, VaultClient};
-use soroban_sdk::{testutils::Address as _, token, Address, BytesN, Env};
+use crate::{FeeQuote, ScheduleStatus, Vault, VaultClient, VestingSchedule};
+use soroban_sdk::{
+    testutils::{Address as _, Ledger, MockAuth, MockAuthInvoke},
+    token, Address, Bytes, BytesN, Env, IntoVal, Vec,
+};
 use user_wallet::{UserWallet, UserWalletClient};
 
 #[test]
@@ -139,7 +142,15 @@ fn test_process_payment() {
 
     let user_wallet_id = env.register(
         UserWallet,
-        (&backend, &contract_id, &usdc, &None::<Address>),
+        (
+            &backend,
+            &contract_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
     );
     let user_wallet_client = UserWalletClient::new(&env, &user_wallet_id);
 
@@ -151,7 +162,12 @@ fn test_process_payment() {
     token_admin_client.mint(&user_wallet_id, &100_000_000);
 
     // Fund the vault via wallet (payment + fee)
-    user_wallet_client.transfer_to_vault(&backend, &50_000_000);
+    user_wallet_client.withdraw(
+        &backend,
+        &50_500_000,
+        &contract_id,
+        &BytesN::from_array(&env, &[2; 32]),
+    );
 
     // Process payment
     let payment_id = BytesN::from_array(&env, &[1u8; 32]);
@@ -169,6 +185,88 @@ fn test_process_payment() {
     assert_eq!(token_client.balance(&user_wallet_id), 49_500_000);
 }
 
+#[test]
+#[should_panic(expected = "Payment already exists")]
+fn test_process_payment_duplicate_id() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let operator = Address::generate(&env);
+    let user_wallet = Address::generate(&env);
+
+    let token_admin = Address::generate(&env);
+    let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let usdc = asset_contract.address();
+
+    let contract_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
+    let client = VaultClient::new(&env, &contract_id);
+    client.grant_role(&admin, &operator, &access_control::OPERATOR_ROLE);
+
+    // Fund two payments, then book both under the same id.
+    token::StellarAssetClient::new(&env, &usdc).mint(&contract_id, &101_000_000);
+
+    let payment_id = BytesN::from_array(&env, &[1u8; 32]);
+    client.process_payment(&operator, &user_wallet, &50_000_000, &payment_id);
+    client.process_payment(&operator, &user_wallet, &50_000_000, &payment_id);
+}
+
+#[test]
+fn test_pending_claim_index() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let operator = Address::generate(&env);
+    let alice = Address::generate(&env);
+    let bob = Address::generate(&env);
+
+    let token_admin = Address::generate(&env);
+    let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let usdc = asset_contract.address();
+
+    let contract_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
+    let client = VaultClient::new(&env, &contract_id);
+    client.grant_role(&admin, &operator, &access_control::OPERATOR_ROLE);
+    token::StellarAssetClient::new(&env, &usdc).mint(&contract_id, &31_500_000);
+
+    let first_id = BytesN::from_array(&env, &[1u8; 32]);
+    let second_id = BytesN::from_array(&env, &[2u8; 32]);
+    let third_id = BytesN::from_array(&env, &[3u8; 32]);
+    client.process_payment(&operator, &alice, &10_000_000, &first_id);
+    client.process_payment(&operator, &bob, &10_000_000, &second_id);
+    client.process_payment(&operator, &alice, &10_000_000, &third_id);
+
+    assert_eq!(client.get_pending_claims_count(), 3);
+    assert_eq!(client.get_all_pending_claims(&1, &10).len(), 2);
+    assert_eq!(
+        client.get_recipient_pending_claims(&alice, &0, &10),
+        Vec::from_array(&env, [first_id.clone(), third_id.clone()])
+    );
+
+    // Removing the first claim moves the last one into its slot.
+    client.claim(&alice, &first_id);
+    assert_eq!(client.get_pending_claims_count(), 2);
+    assert_eq!(
+        client.get_all_pending_claims(&0, &10),
+        Vec::from_array(&env, [third_id.clone(), second_id.clone()])
+    );
+    assert_eq!(
+        client.get_recipient_pending_claims(&alice, &0, &10),
+        Vec::from_array(&env, [third_id.clone()])
+    );
+    assert_eq!(
+        client.get_recipient_pending_claims(&bob, &0, &10),
+        Vec::from_array(&env, [second_id])
+    );
+
+    client.claim(&alice, &third_id);
+    assert_eq!(
+        client.get_recipient_pending_claims(&alice, &0, &10).len(),
+        0
+    );
+}
+
 #[test]
 #[should_panic(expected = "Missing required role")]
 fn test_process_payment_not_operator() {
@@ -246,7 +344,15 @@ fn test_verify_vault_accounting() {
 
     let user_wallet_id = env.register(
         UserWallet,
-        (&backend, &contract_id, &usdc, &None::<Address>),
+        (
+            &backend,
+            &contract_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
     );
     let user_wallet_client = UserWalletClient::new(&env, &user_wallet_id);
 
@@ -255,7 +361,12 @@ fn test_verify_vault_accounting() {
     token_admin_client.mint(&user_wallet_id, &100_000_000);
 
     // Fund the vault via wallet (payment + fee)
-    user_wallet_client.transfer_to_vault(&backend, &50_000_000);
+    user_wallet_client.withdraw(
+        &backend,
+        &50_500_000,
+        &contract_id,
+        &BytesN::from_array(&env, &[3; 32]),
+    );
 
     let payment_id = BytesN::from_array(&env, &[1u8; 32]);
     client.process_payment(&operator, &user_wallet_id, &50_000_000, &payment_id);
@@ -282,7 +393,15 @@ fn test_refund_payment_with_fee() {
 
     let user_wallet_id = env.register(
         UserWallet,
-        (&backend, &contract_id, &usdc, &None::<Address>),
+        (
+            &backend,
+            &contract_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
     );
     let user_wallet_client = UserWalletClient::new(&env, &user_wallet_id);
 
@@ -291,7 +410,12 @@ fn test_refund_payment_with_fee() {
     // Mint and process payment
     let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
     token_admin_client.mint(&user_wallet_id, &100_000_000);
-    user_wallet_client.transfer_to_vault(&backend, &50_000_000);
+    user_wallet_client.withdraw(
+        &backend,
+        &50_500_000,
+        &contract_id,
+        &BytesN::from_array(&env, &[4; 32]),
+    );
 
     let payment_id = BytesN::from_array(&env, &[1u8; 32]);
     client.process_payment(&operator, &user_wallet_id, &50_000_000, &payment_id);
@@ -329,7 +453,15 @@ fn test_refund_payment_without_fee() {
 
     let user_wallet_id = env.register(
         UserWallet,
-        (&backend, &contract_id, &usdc, &None::<Address>),
+        (
+            &backend,
+            &contract_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
     );
     let user_wallet_client = UserWalletClient::new(&env, &user_wallet_id);
 
@@ -338,7 +470,12 @@ fn test_refund_payment_without_fee() {
     // Mint and process payment
     let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
     token_admin_client.mint(&user_wallet_id, &100_000_000);
-    user_wallet_client.transfer_to_vault(&backend, &50_000_000);
+    user_wallet_client.withdraw(
+        &backend,
+        &50_500_000,
+        &contract_id,
+        &BytesN::from_array(&env, &[5; 32]),
+    );
 
     let payment_id = BytesN::from_array(&env, &[1u8; 32]);
     client.process_payment(&operator, &user_wallet_id, &50_000_000, &payment_id);
@@ -378,7 +515,15 @@ fn test_withdraw_vault_funds() {
 
     let user_wallet_id = env.register(
         UserWallet,
-        (&backend, &contract_id, &usdc, &None::<Address>),
+        (
+            &backend,
+            &contract_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
     );
     let user_wallet_client = UserWalletClient::new(&env, &user_wallet_id);
 
@@ -388,7 +533,12 @@ fn test_withdraw_vault_funds() {
     // Process a payment
     let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
     token_admin_client.mint(&user_wallet_id, &100_000_000);
-    user_wallet_client.transfer_to_vault(&backend, &50_000_000);
+    user_wallet_client.withdraw(
+        &backend,
+        &50_500_000,
+        &contract_id,
+        &BytesN::from_array(&env, &[6; 32]),
+    );
 
     let payment_id = BytesN::from_array(&env, &[1u8; 32]);
     client.process_payment(&operator, &user_wallet_id, &50_000_000, &payment_id);
@@ -525,7 +675,15 @@ fn test_cancel_pending_claim_by_admin_with_force() {
 
     let user_wallet_id = env.register(
         UserWallet,
-        (&backend, &contract_id, &usdc, &None::<Address>),
+        (
+            &backend,
+            &contract_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
     );
     let user_wallet_client = UserWalletClient::new(&env, &user_wallet_id);
 
@@ -533,7 +691,12 @@ fn test_cancel_pending_claim_by_admin_with_force() {
 
     let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
     token_admin_client.mint(&user_wallet_id, &100_000_000);
-    user_wallet_client.transfer_to_vault(&backend, &50_000_000);
+    user_wallet_client.withdraw(
+        &backend,
+        &50_500_000,
+        &contract_id,
+        &BytesN::from_array(&env, &[7; 32]),
+    );
 
     let payment_id = BytesN::from_array(&env, &[2u8; 32]);
     client.process_payment(&operator, &user_wallet_id, &50_000_000, &payment_id);
@@ -568,7 +731,15 @@ fn test_cancel_pending_claim_by_operator_with_force() {
 
     let user_wallet_id = env.register(
         UserWallet,
-        (&backend, &contract_id, &usdc, &None::<Address>),
+        (
+            &backend,
+            &contract_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
     );
     let user_wallet_client = UserWalletClient::new(&env, &user_wallet_id);
 
@@ -576,7 +747,12 @@ fn test_cancel_pending_claim_by_operator_with_force() {
 
     let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
     token_admin_client.mint(&user_wallet_id, &100_000_000);
-    user_wallet_client.transfer_to_vault(&backend, &50_000_000);
+    user_wallet_client.withdraw(
+        &backend,
+        &50_500_000,
+        &contract_id,
+        &BytesN::from_array(&env, &[8; 32]),
+    );
 
     let payment_id = BytesN::from_array(&env, &[3u8; 32]);
     client.process_payment(&operator, &user_wallet_id, &50_000_000, &payment_id);
@@ -608,7 +784,15 @@ fn test_cancel_pending_claim_not_expired_without_force() {
 
     let user_wallet_id = env.register(
         UserWallet,
-        (&backend, &contract_id, &usdc, &None::<Address>),
+        (
+            &backend,
+            &contract_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
     );
     let user_wallet_client = UserWalletClient::new(&env, &user_wallet_id);
 
@@ -616,7 +800,12 @@ fn test_cancel_pending_claim_not_expired_without_force() {
 
     let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
     token_admin_client.mint(&user_wallet_id, &100_000_000);
-    user_wallet_client.transfer_to_vault(&backend, &50_000_000);
+    user_wallet_client.withdraw(
+        &backend,
+        &50_500_000,
+        &contract_id,
+        &BytesN::from_array(&env, &[9; 32]),
+    );
 
     let payment_id = BytesN::from_array(&env, &[5u8; 32]);
     client.process_payment(&operator, &user_wallet_id, &50_000_000, &payment_id);
@@ -644,7 +833,15 @@ fn test_cancel_pending_claim_unauthorized() {
 
     let user_wallet_id = env.register(
         UserWallet,
-        (&backend, &contract_id, &usdc, &None::<Address>),
+        (
+            &backend,
+            &contract_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
     );
     let user_wallet_client = UserWalletClient::new(&env, &user_wallet_id);
 
@@ -652,7 +849,12 @@ fn test_cancel_pending_claim_unauthorized() {
 
     let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
     token_admin_client.mint(&user_wallet_id, &100_000_000);
-    user_wallet_client.transfer_to_vault(&backend, &50_000_000);
+    user_wallet_client.withdraw(
+        &backend,
+        &50_500_000,
+        &contract_id,
+        &BytesN::from_array(&env, &[10; 32]),
+    );
 
     let payment_id = BytesN::from_array(&env, &[6u8; 32]);
     client.process_payment(&operator, &user_wallet_id, &50_000_000, &payment_id);
@@ -694,7 +896,15 @@ fn test_cancel_pending_claim_accounting_reversed() {
 
     let user_wallet_id = env.register(
         UserWallet,
-        (&backend, &contract_id, &usdc, &None::<Address>),
+        (
+            &backend,
+            &contract_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
     );
     let user_wallet_client = UserWalletClient::new(&env, &user_wallet_id);
 
@@ -702,7 +912,12 @@ fn test_cancel_pending_claim_accounting_reversed() {
 
     let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
     token_admin_client.mint(&user_wallet_id, &100_000_000);
-    user_wallet_client.transfer_to_vault(&backend, &50_000_000);
+    user_wallet_client.withdraw(
+        &backend,
+        &50_500_000,
+        &contract_id,
+        &BytesN::from_array(&env, &[11; 32]),
+    );
 
     let payment_id = BytesN::from_array(&env, &[7u8; 32]);
     client.process_payment(&operator, &user_wallet_id, &50_000_000, &payment_id);
@@ -717,3 +932,939 @@ fn test_cancel_pending_claim_accounting_reversed() {
     assert_eq!(total, 0);
     assert!(client.verify_vault_accounting());
 }
+
+// --- vesting payment tests ---
+
+fn setup_vesting_payment(
+    env: &Env,
+    cliff_ledger: Option<u32>,
+) -> (
+    VaultClient<'static>,
+    Address,
+    Address,
+    Address,
+    Address,
+    BytesN<32>,
+) {
+    let admin = Address::generate(env);
+    let operator = Address::generate(env);
+    let backend = Address::generate(env);
+    let contractor = Address::generate(env);
+
+    let token_admin = Address::generate(env);
+    let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let usdc = asset_contract.address();
+
+    let contract_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
+    let client = VaultClient::new(env, &contract_id);
+
+    let user_wallet_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &contract_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let user_wallet_client = UserWalletClient::new(env, &user_wallet_id);
+
+    client.grant_role(&admin, &operator, &access_control::OPERATOR_ROLE);
+
+    let token_admin_client = token::StellarAssetClient::new(env, &usdc);
+    token_admin_client.mint(&user_wallet_id, &100_000_000);
+    user_wallet_client.withdraw(
+        &backend,
+        &50_500_000,
+        &contract_id,
+        &BytesN::from_array(env, &[12; 32]),
+    );
+
+    env.ledger().set_sequence_number(100);
+    let payment_id = BytesN::from_array(env, &[8u8; 32]);
+    let schedule = VestingSchedule {
+        payer: user_wallet_id.clone(),
+        start_ledger: 100,
+        end_ledger: 1_100,
+        cliff_ledger,
+    };
+    client.process_vesting_payment(&operator, &contractor, &50_000_000, &payment_id, &schedule);
+
+    (client, admin, usdc, user_wallet_id, contractor, payment_id)
+}
+
+#[test]
+fn test_vesting_claim_releases_vested_portion() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (client, _admin, usdc, _payer, contractor, payment_id) = setup_vesting_payment(&env, None);
+    let token_client = token::Client::new(&env, &usdc);
+
+    env.ledger().set_sequence_number(350);
+    assert_eq!(client.get_claimable_amount(&payment_id), 12_500_000);
+    client.claim(&contractor, &payment_id);
+    assert_eq!(token_client.balance(&contractor), 12_500_000);
+
+    let claim = client.get_pending_claim(&payment_id).unwrap();
+    assert_eq!(claim.claimed_amount, 12_500_000);
+
+    let (payments, fees, _) = client.get_available_withdrawal();
+    assert_eq!(payments, 37_500_000);
+    assert_eq!(fees, 500_000);
+
+    env.ledger().set_sequence_number(1_100);
+    client.claim(&contractor, &payment_id);
+    assert_eq!(token_client.balance(&contractor), 50_000_000);
+    assert!(client.get_pending_claim(&payment_id).is_none());
+    assert_eq!(client.get_pending_claims_count(), 0);
+}
+
+#[test]
+fn test_open_claims_total_follows_claims_and_cancel() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (client, _admin, _usdc, payer, contractor, payment_id) = setup_vesting_payment(&env, None);
+    assert_eq!(client.get_accounting_report().open_claims, 50_000_000);
+
+    env.ledger().set_sequence_number(350);
+    client.claim(&contractor, &payment_id);
+    assert_eq!(client.get_accounting_report().open_claims, 37_500_000);
+
+    client.cancel_vesting_payment(&payer, &payment_id);
+    assert_eq!(client.get_accounting_report().open_claims, 0);
+    client.check_accounting_invariants();
+}
+
+#[test]
+#[should_panic(expected = "Nothing vested to claim")]
+fn test_vesting_claim_before_cliff() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (client, _admin, _usdc, _payer, contractor, payment_id) =
+        setup_vesting_payment(&env, Some(600));
+
+    env.ledger().set_sequence_number(599);
+    client.claim(&contractor, &payment_id);
+}
+
+#[test]
+fn test_cancel_vesting_payment_refunds_unvested() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (client, _admin, usdc, payer, contractor, payment_id) = setup_vesting_payment(&env, None);
+    let token_client = token::Client::new(&env, &usdc);
+
+    env.ledger().set_sequence_number(300);
+    client.claim(&contractor, &payment_id);
+    assert_eq!(token_client.balance(&contractor), 10_000_000);
+
+    env.ledger().set_sequence_number(600);
+    client.cancel_vesting_payment(&payer, &payment_id);
+
+    // Vested-but-unclaimed goes to the contractor, the rest back to the payer.
+    assert_eq!(token_client.balance(&contractor), 25_000_000);
+    assert_eq!(token_client.balance(&payer), 49_500_000 + 25_000_000);
+    assert!(client.get_pending_claim(&payment_id).is_none());
+    assert!(client.get_vesting_schedule(&payment_id).is_none());
+
+    // The fee was collected and is still backed by the vault balance.
+    let (payments, fees, _) = client.get_available_withdrawal();
+    assert_eq!(payments, 0);
+    assert_eq!(fees, 500_000);
+    assert_eq!(token_client.balance(&client.address), 500_000);
+    client.check_accounting_invariants();
+}
+
+#[test]
+#[should_panic(expected = "Only payer or admin")]
+fn test_cancel_vesting_payment_not_payer() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (client, _admin, _usdc, _payer, contractor, payment_id) = setup_vesting_payment(&env, None);
+
+    client.cancel_vesting_payment(&contractor, &payment_id);
+}
+
+// --- recurring schedule tests ---
+
+fn setup_recurring_schedule(
+    env: &Env,
+) -> (
+    VaultClient<'static>,
+    UserWalletClient<'static>,
+    Address,
+    Address,
+    Address,
+    u64,
+) {
+    let admin = Address::generate(env);
+    let backend = Address::generate(env);
+    let merchant = Address::generate(env);
+
+    let token_admin = Address::generate(env);
+    let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let usdc = asset_contract.address();
+
+    let contract_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
+    let client = VaultClient::new(env, &contract_id);
+
+    let user_wallet_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &contract_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let user_wallet_client = UserWalletClient::new(env, &user_wallet_id);
+
+    let token_admin_client = token::StellarAssetClient::new(env, &usdc);
+    token_admin_client.mint(&user_wallet_id, &100_000_000);
+
+    let owner = Address::generate(env);
+    user_wallet_client.set_owner(&backend, &owner, &BytesN::from_array(env, &[20; 32]));
+
+    env.ledger().set_sequence_number(100);
+    user_wallet_client.approve_vault(
+        &owner,
+        &31_500_000,
+        &10_000,
+        &BytesN::from_array(env, &[21; 32]),
+    );
+    let schedule_id = user_wallet_client.create_recurring_payment(
+        &owner,
+        &merchant,
+        &10_000_000,
+        &100,
+        &3,
+        &BytesN::from_array(env, &[22; 32]),
+    );
+
+    (
+        client,
+        user_wallet_client,
+        backend,
+        usdc,
+        merchant,
+        schedule_id,
+    )
+}
+
+#[test]
+fn test_recurring_schedule_executes_each_cycle() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (client, wallet, _backend, usdc, merchant, schedule_id) = setup_recurring_schedule(&env);
+    let token_client = token::Client::new(&env, &usdc);
+
+    let payment_id = client.execute_due(&schedule_id);
+    assert_eq!(token_client.balance(&wallet.address), 89_500_000);
+
+    let (payments, fees, _) = client.get_available_withdrawal();
+    assert_eq!(payments, 10_000_000);
+    assert_eq!(fees, 500_000);
+
+    client.claim(&merchant, &payment_id);
+    assert_eq!(token_client.balance(&merchant), 10_000_000);
+
+    env.ledger().set_sequence_number(200);
+    client.execute_due(&schedule_id);
+    env.ledger().set_sequence_number(300);
+    client.execute_due(&schedule_id);
+
+    // Completed schedules are dropped from storage.
+    assert!(client.get_schedule(&schedule_id).is_none());
+    assert_eq!(token_client.balance(&wallet.address), 68_500_000);
+}
+
+#[test]
+fn test_recurring_schedule_skips_missed_periods() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (client, wallet, _backend, usdc, _merchant, schedule_id) = setup_recurring_schedule(&env);
+    let token_client = token::Client::new(&env, &usdc);
+
+    client.execute_due(&schedule_id);
+
+    // Two and a half periods later only one cycle is paid; the next one
+    // stays on the original cadence.
+    env.ledger().set_sequence_number(350);
+    client.execute_due(&schedule_id);
+    assert_eq!(token_client.balance(&wallet.address), 79_000_000);
+
+    let schedule = client.get_schedule(&schedule_id).unwrap();
+    assert_eq!(schedule.cycles_executed, 2);
+    assert_eq!(schedule.next_ledger, 400);
+
+    let result = client.try_execute_due(&schedule_id);
+    assert!(result.is_err());
+}
+
+#[test]
+#[should_panic(expected = "Payment already exists")]
+fn test_recurring_schedule_rejects_colliding_payment_id() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (client, _wallet, _backend, usdc, merchant, schedule_id) = setup_recurring_schedule(&env);
+
+    // Book a claim under the id the first cycle will derive.
+    let mut payment_seed = Bytes::from_slice(&env, b"RECURRING");
+    payment_seed.extend_from_array(&schedule_id.to_be_bytes());
+    payment_seed.extend_from_array(&0u32.to_be_bytes());
+    let payment_id: BytesN<32> = env.crypto().sha256(&payment_seed).into();
+
+    let operator = Address::generate(&env);
+    env.as_contract(&client.address, || {
+        access_control::grant_role(&env, operator.clone(), access_control::OPERATOR_ROLE);
+    });
+    token::StellarAssetClient::new(&env, &usdc).mint(&client.address, &10_500_000);
+    client.process_payment(&operator, &merchant, &10_000_000, &payment_id);
+
+    client.execute_due(&schedule_id);
+}
+
+#[test]
+#[should_panic(expected = "Only owner")]
+fn test_backend_cannot_create_recurring_payment() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (_client, wallet, backend, _usdc, merchant, _schedule_id) = setup_recurring_schedule(&env);
+
+    wallet.create_recurring_payment(
+        &backend,
+        &merchant,
+        &90_000_000,
+        &1,
+        &10,
+        &BytesN::from_array(&env, &[23; 32]),
+    );
+}
+
+#[test]
+#[should_panic(expected = "Schedule is not due")]
+fn test_recurring_schedule_not_due() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (client, _wallet, _backend, _usdc, _merchant, schedule_id) = setup_recurring_schedule(&env);
+
+    client.execute_due(&schedule_id);
+    env.ledger().set_sequence_number(199);
+    client.execute_due(&schedule_id);
+}
+
+#[test]
+#[should_panic(expected = "Schedule is not active")]
+fn test_recurring_schedule_paused_by_payer() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (client, wallet, backend, _usdc, _merchant, schedule_id) = setup_recurring_schedule(&env);
+
+    wallet.set_recurring_payment_paused(
+        &backend,
+        &schedule_id,
+        &true,
+        &BytesN::from_array(&env, &[60; 32]),
+    );
+    assert_eq!(
+        client.get_schedule(&schedule_id).unwrap().status,
+        ScheduleStatus::Paused
+    );
+
+    client.execute_due(&schedule_id);
+}
+
+#[test]
+fn test_recurring_schedule_cancelled_by_payer() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (client, wallet, backend, _usdc, _merchant, schedule_id) = setup_recurring_schedule(&env);
+
+    client.execute_due(&schedule_id);
+    wallet.cancel_recurring_payment(&backend, &schedule_id, &BytesN::from_array(&env, &[61; 32]));
+
+    assert!(client.get_schedule(&schedule_id).is_none());
+    let result = client.try_execute_due(&schedule_id);
+    assert!(result.is_err());
+}
+
+// --- deposit_and_process tests ---
+
+#[test]
+fn test_deposit_and_process_pulls_from_allowance() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let operator = Address::generate(&env);
+    let backend = Address::generate(&env);
+    let donor = Address::generate(&env);
+
+    let token_admin = Address::generate(&env);
+    let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let usdc = asset_contract.address();
+
+    let contract_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
+    let client = VaultClient::new(&env, &contract_id);
+
+    let user_wallet_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &contract_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let user_wallet_client = UserWalletClient::new(&env, &user_wallet_id);
+
+    client.grant_role(&admin, &operator, &access_control::OPERATOR_ROLE);
+
+    let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
+    token_admin_client.mint(&user_wallet_id, &100_000_000);
+    // Tokens donated to the vault must not count as funding for a payment
+    token_admin_client.mint(&donor, &5_000_000);
+    token::Client::new(&env, &usdc).transfer(&donor, &contract_id, &5_000_000);
+
+    let owner = Address::generate(&env);
+    user_wallet_client.set_owner(&backend, &owner, &BytesN::from_array(&env, &[20; 32]));
+    user_wallet_client.approve_vault(
+        &owner,
+        &61_000_000,
+        &1_000,
+        &BytesN::from_array(&env, &[21; 32]),
+    );
+
+    let first_id = BytesN::from_array(&env, &[10u8; 32]);
+    let second_id = BytesN::from_array(&env, &[11u8; 32]);
+    client.deposit_and_process(&operator, &user_wallet_id, &40_000_000, &first_id);
+    client.deposit_and_process(&operator, &user_wallet_id, &20_000_000, &second_id);
+
+    let (payments, fees, total) = client.get_available_withdrawal();
+    assert_eq!(payments, 60_000_000);
+    assert_eq!(fees, 1_000_000);
+    assert_eq!(total, 61_000_000);
+
+    let token_client = token::Client::new(&env, &usdc);
+    assert_eq!(token_client.balance(&user_wallet_id), 39_000_000);
+    assert_eq!(token_client.balance(&contract_id), 66_000_000);
+    assert_eq!(token_client.allowance(&user_wallet_id, &contract_id), 0);
+    assert_eq!(client.get_pending_claims_count(), 2);
+}
+
+#[test]
+#[should_panic(expected = "Error(Contract, #9)")]
+fn test_deposit_and_process_without_allowance() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let operator = Address::generate(&env);
+    let backend = Address::generate(&env);
+
+    let token_admin = Address::generate(&env);
+    let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let usdc = asset_contract.address();
+
+    let contract_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
+    let client = VaultClient::new(&env, &contract_id);
+
+    let user_wallet_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &contract_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+
+    client.grant_role(&admin, &operator, &access_control::OPERATOR_ROLE);
+
+    let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
+    token_admin_client.mint(&user_wallet_id, &100_000_000);
+
+    let payment_id = BytesN::from_array(&env, &[12u8; 32]);
+    client.deposit_and_process(&operator, &user_wallet_id, &40_000_000, &payment_id);
+}
+
+#[test]
+fn test_deposit_and_process_requires_wallet_auth() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (client, wallet, _backend, usdc, _merchant, _schedule_id) = setup_recurring_schedule(&env);
+    let operator = Address::generate(&env);
+    client.grant_role(
+        &client.get_admin(),
+        &operator,
+        &access_control::OPERATOR_ROLE,
+    );
+
+    // Only the operator signs: the recurring allowance must stay untouched.
+    let payment_id = BytesN::from_array(&env, &[13u8; 32]);
+    env.mock_auths(&[MockAuth {
+        address: &operator,
+        invoke: &MockAuthInvoke {
+            contract: &client.address,
+            fn_name: "deposit_and_process",
+            args: (
+                operator.clone(),
+                wallet.address.clone(),
+                30_000_000_i128,
+                payment_id.clone(),
+            )
+                .into_val(&env),
+            sub_invokes: &[],
+        },
+    }]);
+    let result =
+        client.try_deposit_and_process(&operator, &wallet.address, &30_000_000, &payment_id);
+    assert!(result.is_err());
+
+    let token_client = token::Client::new(&env, &usdc);
+    assert_eq!(token_client.balance(&wallet.address), 100_000_000);
+    assert_eq!(
+        token_client.allowance(&wallet.address, &client.address),
+        31_500_000
+    );
+}
+
+#[test]
+fn test_deposit_and_process_through_user_wallet() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let (client, wallet, backend, usdc, _merchant, _schedule_id) = setup_recurring_schedule(&env);
+    let operator = Address::generate(&env);
+    client.grant_role(
+        &client.get_admin(),
+        &operator,
+        &access_control::OPERATOR_ROLE,
+    );
+
+    // The backend signs the wallet call and the operator the vault call; the
+    // wallet itself authorizes by being the caller.
+    let payment_id = BytesN::from_array(&env, &[14u8; 32]);
+    let vault_args = (
+        operator.clone(),
+        wallet.address.clone(),
+        30_000_000_i128,
+        payment_id.clone(),
+    )
+        .into_val(&env);
+    let vault_invoke = MockAuthInvoke {
+        contract: &client.address,
+        fn_name: "deposit_and_process",
+        args: vault_args,
+        sub_invokes: &[],
+    };
+    env.mock_auths(&[
+        MockAuth {
+            address: &backend,
+            invoke: &MockAuthInvoke {
+                contract: &wallet.address,
+                fn_name: "deposit_to_vault",
+                args: (
+                    backend.clone(),
+                    operator.clone(),
+                    30_000_000_i128,
+                    payment_id.clone(),
+                )
+                    .into_val(&env),
+                sub_invokes: &[],
+            },
+        },
+        MockAuth {
+            address: &operator,
+            invoke: &vault_invoke,
+        },
+    ]);
+    wallet.deposit_to_vault(&backend, &operator, &30_000_000, &payment_id);
+
+    let token_client = token::Client::new(&env, &usdc);
+    assert_eq!(token_client.balance(&wallet.address), 69_500_000);
+    assert_eq!(
+        token_client.allowance(&wallet.address, &client.address),
+        1_000_000
+    );
+    assert!(client.get_pending_claim(&payment_id).is_some());
+    assert!(wallet.was_executed(&payment_id));
+}
+
+// --- accounting report tests ---
+
+#[test]
+fn test_accounting_report_and_recover_surplus() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let operator = Address::generate(&env);
+    let backend = Address::generate(&env);
+    let donor = Address::generate(&env);
+    let treasury_wallet = Address::generate(&env);
+
+    let token_admin = Address::generate(&env);
+    let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let usdc = asset_contract.address();
+
+    let contract_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
+    let client = VaultClient::new(&env, &contract_id);
+
+    let user_wallet_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &contract_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let user_wallet_client = UserWalletClient::new(&env, &user_wallet_id);
+
+    client.grant_role(&admin, &operator, &access_control::OPERATOR_ROLE);
+
+    let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
+    token_admin_client.mint(&user_wallet_id, &100_000_000);
+    user_wallet_client.withdraw(
+        &backend,
+        &50_500_000,
+        &contract_id,
+        &BytesN::from_array(&env, &[13; 32]),
+    );
+
+    let payment_id = BytesN::from_array(&env, &[13u8; 32]);
+    client.process_payment(&operator, &user_wallet_id, &50_000_000, &payment_id);
+
+    token_admin_client.mint(&donor, &2_000_000);
+    token::Client::new(&env, &usdc).transfer(&donor, &contract_id, &2_000_000);
+
+    let report = client.get_accounting_report();
+    assert_eq!(report.token_balance, 52_500_000);
+    assert_eq!(report.available_payments, 50_000_000);
+    assert_eq!(report.available_fees, 500_000);
+    assert_eq!(report.open_claims, 50_000_000);
+    assert_eq!(report.surplus, 2_000_000);
+
+    assert_eq!(client.recover_surplus(&admin, &treasury_wallet), 2_000_000);
+
+    let token_client = token::Client::new(&env, &usdc);
+    assert_eq!(token_client.balance(&treasury_wallet), 2_000_000);
+    assert_eq!(client.get_accounting_report().surplus, 0);
+    client.check_accounting_invariants();
+}
+
+#[test]
+#[should_panic(expected = "No surplus to recover")]
+fn test_recover_surplus_without_surplus() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let treasury_wallet = Address::generate(&env);
+
+    let token_admin = Address::generate(&env);
+    let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let usdc = asset_contract.address();
+
+    let contract_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
+    let client = VaultClient::new(&env, &contract_id);
+
+    client.recover_surplus(&admin, &treasury_wallet);
+}
+
+#[test]
+#[should_panic(expected = "Open claims exceed available payments")]
+fn test_invariant_open_claims_exceed_available_payments() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let operator = Address::generate(&env);
+    let treasurer = Address::generate(&env);
+    let backend = Address::generate(&env);
+    let treasury_wallet = Address::generate(&env);
+
+    let token_admin = Address::generate(&env);
+    let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let usdc = asset_contract.address();
+
+    let contract_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
+    let client = VaultClient::new(&env, &contract_id);
+
+    let user_wallet_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &contract_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let user_wallet_client = UserWalletClient::new(&env, &user_wallet_id);
+
+    client.grant_role(&admin, &operator, &access_control::OPERATOR_ROLE);
+    client.grant_role(&admin, &treasurer, &access_control::TREASURER_ROLE);
+
+    let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
+    token_admin_client.mint(&user_wallet_id, &100_000_000);
+    user_wallet_client.withdraw(
+        &backend,
+        &50_500_000,
+        &contract_id,
+        &BytesN::from_array(&env, &[14; 32]),
+    );
+
+    let payment_id = BytesN::from_array(&env, &[14u8; 32]);
+    client.process_payment(&operator, &user_wallet_id, &50_000_000, &payment_id);
+
+    // Withdrawing everything leaves the open claim unbacked
+    client.withdraw_vault_funds(&treasurer, &treasury_wallet);
+    assert!(!client.verify_vault_accounting());
+
+    client.check_accounting_invariants();
+}
+
+// --- rescue_token tests ---
+
+#[test]
+fn test_rescue_foreign_token() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let recipient = Address::generate(&env);
+
+    let token_admin = Address::generate(&env);
+    let usdc = env
+        .register_stellar_asset_contract_v2(token_admin.clone())
+        .address();
+    let other = env
+        .register_stellar_asset_contract_v2(token_admin.clone())
+        .address();
+
+    let contract_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
+    let client = VaultClient::new(&env, &contract_id);
+
+    token::StellarAssetClient::new(&env, &other).mint(&contract_id, &7_000_000);
+
+    client.rescue_token(&admin, &other, &recipient, &7_000_000);
+
+    assert_eq!(
+        token::Client::new(&env, &other).balance(&recipient),
+        7_000_000
+    );
+}
+
+#[test]
+#[should_panic(expected = "Amount exceeds unaccounted balance")]
+fn test_rescue_settlement_asset_limited_to_surplus() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let operator = Address::generate(&env);
+    let backend = Address::generate(&env);
+    let recipient = Address::generate(&env);
+
+    let token_admin = Address::generate(&env);
+    let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+    let usdc = asset_contract.address();
+
+    let contract_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
+    let client = VaultClient::new(&env, &contract_id);
+
+    let user_wallet_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &contract_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let user_wallet_client = UserWalletClient::new(&env, &user_wallet_id);
+
+    client.grant_role(&admin, &operator, &access_control::OPERATOR_ROLE);
+
+    let token_admin_client = token::StellarAssetClient::new(&env, &usdc);
+    token_admin_client.mint(&user_wallet_id, &100_000_000);
+    user_wallet_client.withdraw(
+        &backend,
+        &50_500_000,
+        &contract_id,
+        &BytesN::from_array(&env, &[15; 32]),
+    );
+
+    let payment_id = BytesN::from_array(&env, &[15u8; 32]);
+    client.process_payment(&operator, &user_wallet_id, &50_000_000, &payment_id);
+
+    client.rescue_token(&admin, &usdc, &recipient, &1);
+}
+
+#[test]
+fn test_wallet_payment_funded_and_claimed() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let backend = Address::generate(&env);
+    let merchant = Address::generate(&env);
+    let usdc = env
+        .register_stellar_asset_contract_v2(Address::generate(&env))
+        .address();
+
+    let contract_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
+    let client = VaultClient::new(&env, &contract_id);
+    let user_wallet_id = env.register(
+        UserWallet,
+        (
+            &backend,
+            &contract_id,
+            &usdc,
+            &None::<Address>,
+            &Vec::<Address>::new(&env),
+            &None::<Address>,
+            &1u32,
+        ),
+    );
+    let user_wallet_client = UserWalletClient::new(&env, &user_wallet_id);
+    token::StellarAssetClient::new(&env, &usdc).mint(&user_wallet_id, &100_000_000);
+
+    // Funding and booking happen in the wallet call; no operator step needed.
+    let payment_id = BytesN::from_array(&env, &[4u8; 32]);
+    let receipt = user_wallet_client.transfer_to_vault(
+        &backend,
+        &50_000_000,
+        &payment_id,
+        &merchant,
+        &500_000,
+    );
+    assert_eq!(receipt.payer, user_wallet_id);
+    assert_eq!(receipt.total_amount, 50_500_000);
+    assert_eq!(
+        client.get_available_withdrawal(),
+        (50_000_000, 500_000, 50_500_000)
+    );
+    client.check_accounting_invariants();
+
+    client.claim(&merchant, &payment_id);
+    assert_eq!(
+        token::Client::new(&env, &usdc).balance(&merchant),
+        50_000_000
+    );
+}
+
+#[test]
+#[should_panic(expected = "Payment amount must be > 0")]
+fn test_wallet_payment_rejects_zero_amount() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let wallet = Address::generate(&env);
+    let usdc = Address::generate(&env);
+
+    let contract_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
+    let client = VaultClient::new(&env, &contract_id);
+
+    let payment_id = BytesN::from_array(&env, &[4u8; 32]);
+    client.process_wallet_payment(&wallet, &Address::generate(&env), &0, &payment_id, &500_000);
+}
+
+#[test]
+fn test_quote_fee() {
+    let env = Env::default();
+    env.mock_all_auths();
+    env.ledger().set_sequence_number(1_000);
+
+    let admin = Address::generate(&env);
+    let usdc = Address::generate(&env);
+
+    let contract_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
+    let client = VaultClient::new(&env, &contract_id);
+
+    let payer = Address::generate(&env);
+    let payment_id = BytesN::from_array(&env, &[1u8; 32]);
+    let quote = FeeQuote {
+        fee_amount: 500_000,
+        valid_until_ledger: 1_060,
+    };
+    assert_eq!(client.quote_fee(&payer, &payer, &payment_id), quote);
+
+    // The quote holds across a fee change until it expires.
+    client.set_fee(&admin, &750_000);
+    env.ledger().set_sequence_number(1_060);
+    assert_eq!(client.quote_fee(&payer, &payer, &payment_id), quote);
+    assert_eq!(client.get_payment_fee(&payer, &payment_id), 500_000);
+    assert_eq!(
+        client.get_payment_fee(&payer, &BytesN::from_array(&env, &[2u8; 32])),
+        750_000
+    );
+    // The pin is per payer: the same id for another payer is unaffected.
+    assert_eq!(
+        client.get_payment_fee(&Address::generate(&env), &payment_id),
+        750_000
+    );
+
+    env.ledger().set_sequence_number(1_061);
+    assert_eq!(client.get_payment_fee(&payer, &payment_id), 750_000);
+    assert_eq!(
+        client.quote_fee(&payer, &payer, &payment_id).fee_amount,
+        750_000
+    );
+}
+
+#[test]
+#[should_panic(expected = "Missing required role")]
+fn test_quote_fee_for_other_payer_requires_operator() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let usdc = Address::generate(&env);
+
+    let contract_id = env.register(Vault, (&admin, &usdc, &500_000i128, &1_000_000i128));
+    let client = VaultClient::new(&env, &contract_id);
+
+    let payment_id = BytesN::from_array(&env, &[1u8; 32]);
+    client.quote_fee(
+        &Address::generate(&env),
+        &Address::generate(&env),
+        &payment_id,
+    );
+}
@@ -20,6 +20,19 @@ pub fn transfer_token(
     client.transfer(from, to, &amount);
 }
 
+/// Transfer tokens from `from` to `to` using `spender`'s allowance
+pub fn transfer_token_from(
+    env: &Env,
+    token_address: &Address,
+    spender: &Address,
+    from: &Address,
+    to: &Address,
+    amount: i128,
+) {
+    let client = token::Client::new(env, token_address);
+    client.transfer_from(spender, from, to, &amount);
+}
+
 /// Check if an account has sufficient balance
 #[allow(dead_code)]
 pub fn has_sufficient_balance(
@@ -151,6 +164,41 @@ mod tests {
         assert_eq!(balance2, 250_0000000);
     }
 
+    #[test]
+    fn test_token_transfer_from_with_allowance() {
+        let env = Env::default();
+        env.mock_all_auths();
+
+        let token_admin = Address::generate(&env);
+        let asset_contract = env.register_stellar_asset_contract_v2(token_admin.clone());
+        let token_address = asset_contract.address();
+        let owner = Address::generate(&env);
+        let spender = Address::generate(&env);
+
+        let token_admin_client = token::StellarAssetClient::new(&env, &token_address);
+        token_admin_client.mint(&owner, &1000_0000000);
+
+        // Approve, then pull part of the allowance to the spender
+        approve_token(&env, &token_address, &owner, &spender, 300_0000000, 1000);
+        transfer_token_from(
+            &env,
+            &token_address,
+            &spender,
+            &owner,
+            &spender,
+            200_0000000,
+        );
+
+        assert_eq!(get_token_balance(&env, &token_address, &owner), 800_0000000);
+        assert_eq!(
+            get_token_balance(&env, &token_address, &spender),
+            200_0000000
+        );
+
+        let token_client = token::Client::new(&env, &token_address);
+        assert_eq!(token_client.allowance(&owner, &spender), 100_0000000);
+    }
+
     #[test]
     #[should_panic(expected = "balance is not sufficient to spend")]
     fn test_transfer_insufficient_balance() {
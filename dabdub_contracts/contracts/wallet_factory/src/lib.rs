@@ -1,21 +1,49 @@
 #![no_std]
 
 use soroban_sdk::{
-    contract, contractevent, contractimpl, contracttype, Address, BytesN, Env, String, Vec,
+    contract, contractclient, contractevent, contractimpl, contracttype, Address, Bytes, BytesN,
+    Env, Symbol, Vec,
 };
 
+#[contractclient(name = "UserWalletClient")]
+pub trait UserWalletTrait {
+    fn upgrade(env: Env, new_wasm_hash: BytesN<32>, version: u32);
+    fn set_frozen(env: Env, frozen: bool);
+    fn compliance_exit(env: Env, to: Address);
+}
+
+/// Most wallets `upgrade_wallets` handles in one call.
+const MAX_UPGRADE_BATCH: u32 = 25;
+/// Most wallets `create_wallets` deploys in one call.
+const MAX_CREATE_BATCH: u32 = 20;
+/// Most wallets `list_wallets` returns in one page.
+const MAX_PAGE_SIZE: u32 = 100;
+/// TTL wallet index entries are extended to when written (~180 days).
+const WALLET_INDEX_TTL_LEDGERS: u32 = 3_110_400;
+
 #[contracttype]
 #[derive(Clone)]
 pub enum DataKey {
-    Admin,
-    Backend,
     Vault,
     UsdcToken,
-    UserWallet(BytesN<32>), // user_id_hash -> wallet address
-    AllWallets,
+    UserWallet(BytesN<32>), // user_id_hash -> wallet address (persistent)
+    WalletAt(u32),          // sequence number -> wallet address (persistent)
+    WalletUser(Address),    // wallet address -> user_id_hash (persistent)
     TotalWallets,
     Paused,
     WalletWasm,
+    Assets,                 // extra assets supported by newly created wallets
+    WalletVersion,          // version of the code at WalletWasm
+    Closed(BytesN<32>),     // user_id_hash whose last wallet was closed (persistent)
+    Generation(BytesN<32>), // user_id_hash -> wallets closed so far (persistent)
+}
+
+/// Per-user outcome of `create_wallets`
+#[contracttype]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum WalletCreation {
+    Created(Address),
+    Existing(Address), // user already had this wallet
 }
 
 // Events
@@ -37,6 +65,46 @@ struct VaultUpdatedEvent {
     new_vault: Address,
 }
 
+#[contractevent(topics = ["FACTORY", "asset_add"])]
+struct AssetAddedEvent {
+    asset: Address,
+}
+
+#[contractevent(topics = ["FACTORY", "asset_rm"])]
+struct AssetRemovedEvent {
+    asset: Address,
+}
+
+#[contractevent(topics = ["FACTORY", "wasm_upd"])]
+struct WalletWasmUpdatedEvent {
+    wasm_hash: BytesN<32>,
+    version: u32,
+}
+
+#[contractevent(topics = ["FACTORY", "wallet_upg"])]
+struct WalletUpgradedEvent {
+    wallet: Address,
+    version: u32,
+}
+
+#[contractevent(topics = ["FACTORY", "closed"])]
+struct WalletClosedEvent {
+    user_id_hash: BytesN<32>,
+    wallet: Address,
+}
+
+#[contractevent(topics = ["FACTORY", "role"])]
+struct RoleGrantedEvent {
+    account: Address,
+    role: Symbol,
+}
+
+#[contractevent(topics = ["FACTORY", "role"])]
+struct RoleRevokedEvent {
+    account: Address,
+    role: Symbol,
+}
+
 #[contract]
 pub struct WalletFactory;
 
@@ -50,8 +118,10 @@ impl WalletFactory {
         usdc_token: Address,
         wallet_wasm_hash: BytesN<32>,
     ) {
-        env.storage().instance().set(&DataKey::Admin, &admin);
-        env.storage().instance().set(&DataKey::Backend, &backend);
+        access_control::set_primary(&env, access_control::ADMIN_ROLE, &admin);
+        access_control::set_primary(&env, access_control::BACKEND_ROLE, &backend);
+        Self::grant(&env, admin, access_control::ADMIN_ROLE);
+        Self::grant(&env, backend, access_control::BACKEND_ROLE);
         env.storage().instance().set(&DataKey::Vault, &vault);
         env.storage()
             .instance()
@@ -59,20 +129,16 @@ impl WalletFactory {
         env.storage()
             .instance()
             .set(&DataKey::WalletWasm, &wallet_wasm_hash);
+        env.storage().instance().set(&DataKey::WalletVersion, &1u32);
         env.storage().instance().set(&DataKey::TotalWallets, &0u32);
         env.storage().instance().set(&DataKey::Paused, &false);
-        env.storage()
-            .instance()
-            .set(&DataKey::AllWallets, &Vec::<Address>::new(&env));
     }
 
-    /// Create a new user wallet
-    pub fn create_wallet(env: Env, caller: Address, user_id: String) -> Address {
-        let backend: Address = env.storage().instance().get(&DataKey::Backend).unwrap();
-        if caller != backend {
-            panic!("Only backend");
-        }
-
+    /// Create a new user wallet. `user_id_hash` is a keyed hash of the user
+    /// id (e.g. HMAC-SHA256 with a secret pepper) computed off-chain, so the
+    /// plaintext id never appears on-chain.
+    pub fn create_wallet(env: Env, caller: Address, user_id_hash: BytesN<32>) -> Address {
+        Self::require_backend(&env, &caller);
         caller.require_auth();
 
         let paused: bool = env
@@ -84,76 +150,113 @@ impl WalletFactory {
             panic!("Factory is paused");
         }
 
-        // Hash user_id
-        let user_id_hash = env.crypto().sha256(&user_id.to_bytes());
-
-        // Check if wallet already exists
-        let wallet_key = DataKey::UserWallet(user_id_hash.clone().into());
-        if env.storage().instance().has(&wallet_key) {
+        // Check if wallet already exists; users whose wallet was closed get a new one
+        let wallet_key = DataKey::UserWallet(user_id_hash.clone());
+        if env.storage().persistent().has(&wallet_key) {
             panic!("Wallet already exists");
         }
 
-        // Get deployment parameters
-        let vault: Address = env.storage().instance().get(&DataKey::Vault).unwrap();
-        let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
-        let wasm_hash: BytesN<32> = env.storage().instance().get(&DataKey::WalletWasm).unwrap();
-
-        // Deploy the wallet contract with unique salt
-        let salt = user_id_hash.to_bytes();
-        let wallet_address = env.deployer().with_current_contract(salt).deploy_v2(
-            wasm_hash,
-            (
-                backend.clone(),
-                vault.clone(),
-                usdc_token.clone(),
-                None::<Address>,
-            ),
-        );
+        Self::deploy_wallet(&env, user_id_hash)
+    }
 
-        // Store mapping
-        env.storage().instance().set(&wallet_key, &wallet_address);
+    /// Create wallets for many users in one call (backend only). Users that
+    /// already have a wallet are reported instead of failing the batch.
+    pub fn create_wallets(
+        env: Env,
+        caller: Address,
+        user_id_hashes: Vec<BytesN<32>>,
+    ) -> Vec<WalletCreation> {
+        Self::require_backend(&env, &caller);
+        caller.require_auth();
 
-        // Add to all wallets list
-        let mut all_wallets: Vec<Address> = env
+        let paused: bool = env
             .storage()
             .instance()
-            .get(&DataKey::AllWallets)
-            .unwrap_or(Vec::new(&env));
-        all_wallets.push_back(wallet_address.clone());
-        env.storage()
-            .instance()
-            .set(&DataKey::AllWallets, &all_wallets);
+            .get(&DataKey::Paused)
+            .unwrap_or(false);
+        if paused {
+            panic!("Factory is paused");
+        }
 
-        // Increment counter
-        let mut total: u32 = env
-            .storage()
-            .instance()
-            .get(&DataKey::TotalWallets)
-            .unwrap();
-        total += 1;
-        env.storage().instance().set(&DataKey::TotalWallets, &total);
+        if user_id_hashes.len() > MAX_CREATE_BATCH {
+            panic!("Batch too large");
+        }
 
-        WalletCreatedEvent {
-            user_id_hash: user_id_hash.into(),
-            wallet: wallet_address.clone(),
+        let mut results = Vec::new(&env);
+        for user_id_hash in user_id_hashes.iter() {
+            let existing: Option<Address> = env
+                .storage()
+                .persistent()
+                .get(&DataKey::UserWallet(user_id_hash.clone()));
+
+            let result = match existing {
+                Some(wallet) => WalletCreation::Existing(wallet),
+                None => WalletCreation::Created(Self::deploy_wallet(&env, user_id_hash)),
+            };
+            results.push_back(result);
         }
-        .publish(&env);
+        results
+    }
 
-        wallet_address
+    /// Get wallet address for a user id hash
+    pub fn get_wallet(env: Env, user_id_hash: BytesN<32>) -> Option<Address> {
+        let wallet_key = DataKey::UserWallet(user_id_hash);
+        env.storage().persistent().get(&wallet_key)
+    }
+
+    /// Check if a user id hash has a wallet
+    pub fn has_wallet(env: Env, user_id_hash: BytesN<32>) -> bool {
+        let wallet_key = DataKey::UserWallet(user_id_hash);
+        env.storage().persistent().has(&wallet_key)
+    }
+
+    /// Address `create_wallet` deploys the user's wallet to. It depends only
+    /// on this factory, the user id hash and how many of the user's wallets
+    /// were closed, so funds sent here before the wallet exists become
+    /// spendable by it once `create_wallet` is called.
+    pub fn predict_wallet_address(env: Env, user_id_hash: BytesN<32>) -> Address {
+        env.deployer()
+            .with_current_contract(Self::wallet_salt(&env, &user_id_hash))
+            .deployed_address()
+    }
+
+    /// Whether the user's last wallet was closed and none was created since
+    pub fn is_user_closed(env: Env, user_id_hash: BytesN<32>) -> bool {
+        env.storage()
+            .persistent()
+            .has(&DataKey::Closed(user_id_hash))
     }
 
-    /// Get wallet address for a user
-    pub fn get_wallet(env: Env, user_id: String) -> Option<Address> {
-        let user_id_hash = env.crypto().sha256(&user_id.to_bytes());
-        let wallet_key = DataKey::UserWallet(user_id_hash.into());
-        env.storage().instance().get(&wallet_key)
+    /// List wallets in creation order, at most 100 per page
+    pub fn list_wallets(env: Env, offset: u32, limit: u32) -> Vec<Address> {
+        let total = Self::get_total_wallets(env.clone());
+        let end = offset.saturating_add(limit.min(MAX_PAGE_SIZE)).min(total);
+
+        let mut wallets = Vec::new(&env);
+        for index in offset..end {
+            let wallet: Address = env
+                .storage()
+                .persistent()
+                .get(&DataKey::WalletAt(index))
+                .unwrap();
+            wallets.push_back(wallet);
+        }
+        wallets
     }
 
-    /// Check if user has a wallet
-    pub fn has_wallet(env: Env, user_id: String) -> bool {
-        let user_id_hash = env.crypto().sha256(&user_id.to_bytes());
-        let wallet_key = DataKey::UserWallet(user_id_hash.into());
-        env.storage().instance().has(&wallet_key)
+    /// Hashed user id a wallet was created for
+    pub fn get_user_hash(env: Env, wallet: Address) -> BytesN<32> {
+        env.storage()
+            .persistent()
+            .get(&DataKey::WalletUser(wallet))
+            .expect("Not a factory wallet")
+    }
+
+    /// Whether `address` is a wallet deployed by this factory
+    pub fn is_factory_wallet(env: Env, address: Address) -> bool {
+        env.storage()
+            .persistent()
+            .has(&DataKey::WalletUser(address))
     }
 
     /// Get total wallets created
@@ -164,19 +267,17 @@ impl WalletFactory {
             .unwrap_or(0)
     }
 
-    /// Update backend address (admin only)
+    /// Replace the primary backend address (admin only), moving its backend
+    /// role. Wallets created by this factory accept every backend operator
+    /// unless their owner pinned a backend.
     pub fn update_backend(env: Env, caller: Address, new_backend: Address) {
-        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
-        if caller != admin {
-            panic!("Only admin");
-        }
-
+        Self::require_admin(&env, &caller);
         caller.require_auth();
 
-        let old_backend: Address = env.storage().instance().get(&DataKey::Backend).unwrap();
-        env.storage()
-            .instance()
-            .set(&DataKey::Backend, &new_backend);
+        let old_backend = Self::get_backend(env.clone());
+        access_control::set_primary(&env, access_control::BACKEND_ROLE, &new_backend);
+        Self::revoke(&env, old_backend.clone(), access_control::BACKEND_ROLE);
+        Self::grant(&env, new_backend.clone(), access_control::BACKEND_ROLE);
 
         BackendUpdatedEvent {
             old_backend,
@@ -185,13 +286,10 @@ impl WalletFactory {
         .publish(&env);
     }
 
-    /// Update vault address (admin only)
+    /// Update vault address (admin only). Wallets created by this factory
+    /// follow it.
     pub fn update_vault(env: Env, caller: Address, new_vault: Address) {
-        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
-        if caller != admin {
-            panic!("Only admin");
-        }
-
+        Self::require_admin(&env, &caller);
         caller.require_auth();
 
         let old_vault: Address = env.storage().instance().get(&DataKey::Vault).unwrap();
@@ -204,37 +302,208 @@ impl WalletFactory {
         .publish(&env);
     }
 
-    /// Pause factory (admin only)
-    pub fn pause(env: Env, caller: Address) {
-        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
-        if caller != admin {
-            panic!("Only admin");
+    /// Set the wallet code used for new wallets and upgrades (admin only).
+    /// Each new code gets the next version number.
+    pub fn set_wallet_wasm(env: Env, caller: Address, wasm_hash: BytesN<32>) -> u32 {
+        Self::require_admin(&env, &caller);
+        caller.require_auth();
+
+        let version = Self::get_wallet_version(env.clone())
+            .checked_add(1)
+            .expect("Version overflow");
+        env.storage()
+            .instance()
+            .set(&DataKey::WalletWasm, &wasm_hash);
+        env.storage()
+            .instance()
+            .set(&DataKey::WalletVersion, &version);
+
+        WalletWasmUpdatedEvent { wasm_hash, version }.publish(&env);
+
+        version
+    }
+
+    /// Upgrade a batch of wallets to the current wallet code (admin only).
+    /// Returns whether each wallet was upgraded; wallets whose owner opted
+    /// out, that are already current or not from this factory are skipped.
+    pub fn upgrade_wallets(env: Env, caller: Address, wallets: Vec<Address>) -> Vec<bool> {
+        Self::require_admin(&env, &caller);
+        caller.require_auth();
+
+        if wallets.len() > MAX_UPGRADE_BATCH {
+            panic!("Batch too large");
         }
 
+        let wasm_hash: BytesN<32> = env.storage().instance().get(&DataKey::WalletWasm).unwrap();
+        let version = Self::get_wallet_version(env.clone());
+
+        let mut results = Vec::new(&env);
+        for wallet in wallets.iter() {
+            let upgraded = matches!(
+                UserWalletClient::new(&env, &wallet).try_upgrade(&wasm_hash, &version),
+                Ok(Ok(()))
+            );
+            if upgraded {
+                WalletUpgradedEvent {
+                    wallet: wallet.clone(),
+                    version,
+                }
+                .publish(&env);
+            }
+            results.push_back(upgraded);
+        }
+        results
+    }
+
+    /// Freeze a wallet deployed by this factory (admin or compliance)
+    pub fn freeze_wallet(env: Env, caller: Address, wallet: Address) {
+        Self::require_compliance(&env, &caller, &wallet);
+        UserWalletClient::new(&env, &wallet).set_frozen(&true);
+    }
+
+    /// Unfreeze a wallet deployed by this factory (admin or compliance)
+    pub fn unfreeze_wallet(env: Env, caller: Address, wallet: Address) {
+        Self::require_compliance(&env, &caller, &wallet);
+        UserWalletClient::new(&env, &wallet).set_frozen(&false);
+    }
+
+    /// Let a frozen wallet's funds leave to `to` (admin or compliance)
+    pub fn approve_exit(env: Env, caller: Address, wallet: Address, to: Address) {
+        Self::require_compliance(&env, &caller, &wallet);
+        UserWalletClient::new(&env, &wallet).compliance_exit(&to);
+    }
+
+    /// Called by a wallet of this factory when its owner closes it. Frees the
+    /// user id hash so `create_wallet` can deploy a new wallet for the user.
+    pub fn wallet_closed(env: Env, wallet: Address) {
+        wallet.require_auth();
+
+        let user_id_hash = Self::get_user_hash(env.clone(), wallet.clone());
+        let current: Option<Address> = env
+            .storage()
+            .persistent()
+            .get(&DataKey::UserWallet(user_id_hash.clone()));
+        if current != Some(wallet.clone()) {
+            panic!("Wallet already closed");
+        }
+
+        let generation_key = DataKey::Generation(user_id_hash.clone());
+        let generation: u32 = env.storage().persistent().get(&generation_key).unwrap_or(0);
+
+        let storage = env.storage().persistent();
+        let closed_key = DataKey::Closed(user_id_hash.clone());
+        storage.remove(&DataKey::UserWallet(user_id_hash.clone()));
+        storage.set(&closed_key, &true);
+        storage.set(&generation_key, &(generation + 1));
+        for key in [closed_key, generation_key].iter() {
+            storage.extend_ttl(key, WALLET_INDEX_TTL_LEDGERS, WALLET_INDEX_TTL_LEDGERS);
+        }
+
+        WalletClosedEvent {
+            user_id_hash,
+            wallet,
+        }
+        .publish(&env);
+    }
+
+    /// Add an asset that new wallets support besides USDC (admin only).
+    /// Existing wallets are not changed.
+    pub fn add_supported_asset(env: Env, caller: Address, asset: Address) {
+        Self::require_admin(&env, &caller);
+        caller.require_auth();
+
+        let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
+        let mut assets = Self::get_supported_assets(env.clone());
+        if asset == usdc_token || assets.contains(&asset) {
+            panic!("Asset already supported");
+        }
+        assets.push_back(asset.clone());
+        env.storage().instance().set(&DataKey::Assets, &assets);
+
+        AssetAddedEvent { asset }.publish(&env);
+    }
+
+    /// Remove an asset from the set given to new wallets (admin only)
+    pub fn remove_supported_asset(env: Env, caller: Address, asset: Address) {
+        Self::require_admin(&env, &caller);
+        caller.require_auth();
+
+        let mut assets = Self::get_supported_assets(env.clone());
+        let index = assets
+            .first_index_of(&asset)
+            .unwrap_or_else(|| panic!("Asset not supported"));
+        assets.remove(index);
+        env.storage().instance().set(&DataKey::Assets, &assets);
+
+        AssetRemovedEvent { asset }.publish(&env);
+    }
+
+    /// Grant a role, e.g. an extra backend operator or compliance (admin only)
+    pub fn grant_role(env: Env, caller: Address, account: Address, role: Symbol) {
+        Self::require_admin(&env, &caller);
+        caller.require_auth();
+
+        Self::grant(&env, account, role);
+    }
+
+    pub fn revoke_role(env: Env, caller: Address, account: Address, role: Symbol) {
+        Self::require_admin(&env, &caller);
+        caller.require_auth();
+
+        Self::revoke(&env, account, role);
+    }
+
+    pub fn has_role(env: Env, account: Address, role: Symbol) -> bool {
+        access_control::has_role(&env, &account, role)
+    }
+
+    /// Whether `account` is one of the backend operators wallets accept
+    pub fn is_backend(env: Env, account: Address) -> bool {
+        access_control::has_role(&env, &account, access_control::BACKEND_ROLE)
+    }
+
+    /// Pause factory (admin only)
+    pub fn pause(env: Env, caller: Address) {
+        Self::require_admin(&env, &caller);
         caller.require_auth();
         env.storage().instance().set(&DataKey::Paused, &true);
     }
 
     /// Unpause factory (admin only)
     pub fn unpause(env: Env, caller: Address) {
-        let admin: Address = env.storage().instance().get(&DataKey::Admin).unwrap();
-        if caller != admin {
-            panic!("Only admin");
-        }
-
+        Self::require_admin(&env, &caller);
         caller.require_auth();
         env.storage().instance().set(&DataKey::Paused, &false);
     }
 
     // View functions
     pub fn get_backend(env: Env) -> Address {
-        env.storage().instance().get(&DataKey::Backend).unwrap()
+        access_control::get_primary(&env, access_control::BACKEND_ROLE).unwrap()
     }
 
     pub fn get_vault(env: Env) -> Address {
         env.storage().instance().get(&DataKey::Vault).unwrap()
     }
 
+    /// Assets besides USDC that new wallets support
+    pub fn get_supported_assets(env: Env) -> Vec<Address> {
+        env.storage()
+            .instance()
+            .get(&DataKey::Assets)
+            .unwrap_or(Vec::new(&env))
+    }
+
+    pub fn get_wallet_wasm(env: Env) -> BytesN<32> {
+        env.storage().instance().get(&DataKey::WalletWasm).unwrap()
+    }
+
+    pub fn get_wallet_version(env: Env) -> u32 {
+        env.storage()
+            .instance()
+            .get(&DataKey::WalletVersion)
+            .unwrap_or(1)
+    }
+
     pub fn is_paused(env: Env) -> bool {
         env.storage()
             .instance()
@@ -243,7 +512,127 @@ impl WalletFactory {
     }
 
     pub fn get_admin(env: Env) -> Address {
-        env.storage().instance().get(&DataKey::Admin).unwrap()
+        access_control::get_primary(&env, access_control::ADMIN_ROLE).unwrap()
+    }
+
+    /// Deployment salt for the user's next wallet: the user id hash for the
+    /// first one, then hashed with the number of wallets closed before it.
+    fn wallet_salt(env: &Env, user_id_hash: &BytesN<32>) -> BytesN<32> {
+        let generation: u32 = env
+            .storage()
+            .persistent()
+            .get(&DataKey::Generation(user_id_hash.clone()))
+            .unwrap_or(0);
+        if generation == 0 {
+            return user_id_hash.clone();
+        }
+
+        let mut preimage = Bytes::from_array(env, &user_id_hash.to_array());
+        preimage.extend_from_array(&generation.to_be_bytes());
+        env.crypto().sha256(&preimage).into()
+    }
+
+    fn require_admin(env: &Env, caller: &Address) {
+        if !access_control::has_role(env, caller, access_control::ADMIN_ROLE) {
+            panic!("Only admin");
+        }
+    }
+
+    fn require_backend(env: &Env, caller: &Address) {
+        if !access_control::has_role(env, caller, access_control::BACKEND_ROLE) {
+            panic!("Only backend");
+        }
+    }
+
+    /// Panic unless `caller` has the admin or compliance role and `wallet`
+    /// was deployed by this factory
+    fn require_compliance(env: &Env, caller: &Address, wallet: &Address) {
+        if !access_control::has_role(env, caller, access_control::ADMIN_ROLE)
+            && !access_control::has_role(env, caller, access_control::COMPLIANCE_ROLE)
+        {
+            panic!("Only admin or compliance");
+        }
+        caller.require_auth();
+
+        if !Self::is_factory_wallet(env.clone(), wallet.clone()) {
+            panic!("Not a factory wallet");
+        }
+    }
+
+    /// Deploy and index the wallet for `user_id_hash`
+    fn deploy_wallet(env: &Env, user_id_hash: BytesN<32>) -> Address {
+        // Get deployment parameters
+        let backend = Self::get_backend(env.clone());
+        let vault: Address = env.storage().instance().get(&DataKey::Vault).unwrap();
+        let usdc_token: Address = env.storage().instance().get(&DataKey::UsdcToken).unwrap();
+        let wasm_hash: BytesN<32> = env.storage().instance().get(&DataKey::WalletWasm).unwrap();
+        let assets = Self::get_supported_assets(env.clone());
+        let version = Self::get_wallet_version(env.clone());
+
+        // Deploy the wallet contract with unique salt
+        let salt = Self::wallet_salt(env, &user_id_hash);
+        let wallet_address = env.deployer().with_current_contract(salt).deploy_v2(
+            wasm_hash,
+            (
+                backend,
+                vault,
+                usdc_token,
+                None::<Address>,
+                assets,
+                Some(env.current_contract_address()),
+                version,
+            ),
+        );
+
+        Self::index_wallet(env, &user_id_hash, &wallet_address);
+        env.storage()
+            .persistent()
+            .remove(&DataKey::Closed(user_id_hash.clone()));
+
+        WalletCreatedEvent {
+            user_id_hash,
+            wallet: wallet_address.clone(),
+        }
+        .publish(env);
+
+        wallet_address
+    }
+
+    /// Record a new wallet under the next sequence number and in both
+    /// lookup directions.
+    fn index_wallet(env: &Env, user_id_hash: &BytesN<32>, wallet: &Address) {
+        let index = Self::get_total_wallets(env.clone());
+        let keys = [
+            DataKey::UserWallet(user_id_hash.clone()),
+            DataKey::WalletAt(index),
+            DataKey::WalletUser(wallet.clone()),
+        ];
+
+        let storage = env.storage().persistent();
+        storage.set(&keys[0], wallet);
+        storage.set(&keys[1], wallet);
+        storage.set(&keys[2], user_id_hash);
+        for key in keys.iter() {
+            storage.extend_ttl(key, WALLET_INDEX_TTL_LEDGERS, WALLET_INDEX_TTL_LEDGERS);
+        }
+
+        env.storage()
+            .instance()
+            .set(&DataKey::TotalWallets, &(index + 1));
+    }
+
+    /// Grant `role` to `account`, emitting an event if it was newly granted
+    fn grant(env: &Env, account: Address, role: Symbol) {
+        if access_control::grant_role(env, account.clone(), role.clone()) {
+            RoleGrantedEvent { account, role }.publish(env);
+        }
+    }
+
+    /// Revoke `role` from `account`, emitting an event if it held the role
+    fn revoke(env: &Env, account: Address, role: Symbol) {
+        if access_control::revoke_role(env, account.clone(), role.clone()) {
+            RoleRevokedEvent { account, role }.publish(env);
+        }
     }
 }
 
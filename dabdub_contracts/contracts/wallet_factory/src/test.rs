@@ -1,6 +1,32 @@
 #![cfg(test)]
-use crate::{WalletFactory, WalletFactoryClient};
-use soroban_sdk::{testutils::Address as _, Address, BytesN, Env, String};
+use crate::{WalletCreation, WalletFactory, WalletFactoryClient};
+use access_control::{ADMIN_ROLE, BACKEND_ROLE, COMPLIANCE_ROLE};
+use soroban_sdk::{testutils::Address as _, token, vec, Address, BytesN, Env, Vec};
+
+#[allow(clippy::too_many_arguments)]
+mod user_wallet {
+    use soroban_sdk::auth::Context;
+    soroban_sdk::contractimport!(file = "../../target/wasm32v1-none/release/user_wallet.wasm");
+}
+
+/// Factory deploying the real user wallet code, with a USDC asset contract
+fn setup_live_factory(env: &Env) -> (WalletFactoryClient<'_>, Address, Address, Address) {
+    let admin = Address::generate(env);
+    let backend = Address::generate(env);
+    let vault = Address::generate(env);
+    let usdc = env
+        .register_stellar_asset_contract_v2(Address::generate(env))
+        .address();
+    let wasm_hash = env.deployer().upload_contract_wasm(user_wallet::WASM);
+
+    let contract_id = env.register(WalletFactory, (&admin, &backend, &vault, &usdc, &wasm_hash));
+    (
+        WalletFactoryClient::new(env, &contract_id),
+        admin,
+        backend,
+        usdc,
+    )
+}
 
 #[test]
 fn test_constructor() {
@@ -35,9 +61,9 @@ fn test_has_wallet_returns_false() {
     let contract_id = env.register(WalletFactory, (&admin, &backend, &vault, &usdc, &wasm_hash));
     let client = WalletFactoryClient::new(&env, &contract_id);
 
-    let user_id = String::from_str(&env, "user@example.com");
-    assert!(!client.has_wallet(&user_id));
-    assert_eq!(client.get_wallet(&user_id), None);
+    let user_id_hash = BytesN::from_array(&env, &[7u8; 32]);
+    assert!(!client.has_wallet(&user_id_hash));
+    assert_eq!(client.get_wallet(&user_id_hash), None);
 }
 
 #[test]
@@ -139,3 +165,557 @@ fn test_pause_not_admin() {
 
     client.pause(&not_admin);
 }
+
+#[test]
+fn test_supported_assets() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let usdc = Address::generate(&env);
+    let eurc = Address::generate(&env);
+    let wasm_hash = BytesN::from_array(&env, &[0u8; 32]);
+
+    let contract_id = env.register(WalletFactory, (&admin, &backend, &vault, &usdc, &wasm_hash));
+    let client = WalletFactoryClient::new(&env, &contract_id);
+    assert_eq!(client.get_supported_assets(), vec![&env]);
+
+    client.add_supported_asset(&admin, &eurc);
+    assert_eq!(client.get_supported_assets(), vec![&env, eurc.clone()]);
+
+    client.remove_supported_asset(&admin, &eurc);
+    assert_eq!(client.get_supported_assets(), vec![&env]);
+}
+
+#[test]
+#[should_panic(expected = "Asset already supported")]
+fn test_add_usdc_as_supported_asset() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let usdc = Address::generate(&env);
+    let wasm_hash = BytesN::from_array(&env, &[0u8; 32]);
+
+    let contract_id = env.register(WalletFactory, (&admin, &backend, &vault, &usdc, &wasm_hash));
+    let client = WalletFactoryClient::new(&env, &contract_id);
+
+    client.add_supported_asset(&admin, &usdc);
+}
+
+#[test]
+fn test_set_wallet_wasm_bumps_version() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let usdc = Address::generate(&env);
+    let wasm_hash = BytesN::from_array(&env, &[0u8; 32]);
+
+    let contract_id = env.register(WalletFactory, (&admin, &backend, &vault, &usdc, &wasm_hash));
+    let client = WalletFactoryClient::new(&env, &contract_id);
+    assert_eq!(client.get_wallet_version(), 1);
+
+    let new_hash = BytesN::from_array(&env, &[1u8; 32]);
+    assert_eq!(client.set_wallet_wasm(&admin, &new_hash), 2);
+    assert_eq!(client.get_wallet_wasm(), new_hash);
+    assert_eq!(client.get_wallet_version(), 2);
+}
+
+#[test]
+#[should_panic(expected = "Only admin")]
+fn test_set_wallet_wasm_not_admin() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let usdc = Address::generate(&env);
+    let wasm_hash = BytesN::from_array(&env, &[0u8; 32]);
+
+    let contract_id = env.register(WalletFactory, (&admin, &backend, &vault, &usdc, &wasm_hash));
+    let client = WalletFactoryClient::new(&env, &contract_id);
+
+    client.set_wallet_wasm(&backend, &BytesN::from_array(&env, &[1u8; 32]));
+}
+
+#[test]
+#[should_panic(expected = "Batch too large")]
+fn test_upgrade_wallets_batch_too_large() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let usdc = Address::generate(&env);
+    let wasm_hash = BytesN::from_array(&env, &[0u8; 32]);
+
+    let contract_id = env.register(WalletFactory, (&admin, &backend, &vault, &usdc, &wasm_hash));
+    let client = WalletFactoryClient::new(&env, &contract_id);
+
+    let mut wallets = Vec::new(&env);
+    for _ in 0..26 {
+        wallets.push_back(Address::generate(&env));
+    }
+    client.upgrade_wallets(&admin, &wallets);
+}
+
+#[test]
+fn test_wallet_index() {
+    let env = Env::default();
+
+    let admin = Address::generate(&env);
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let usdc = Address::generate(&env);
+    let wasm_hash = BytesN::from_array(&env, &[0u8; 32]);
+
+    let contract_id = env.register(WalletFactory, (&admin, &backend, &vault, &usdc, &wasm_hash));
+    let client = WalletFactoryClient::new(&env, &contract_id);
+    assert_eq!(client.list_wallets(&0, &10), vec![&env]);
+
+    let mut wallets = Vec::new(&env);
+    for i in 0..3u8 {
+        let wallet = Address::generate(&env);
+        let user_id_hash = BytesN::from_array(&env, &[i; 32]);
+        env.as_contract(&contract_id, || {
+            WalletFactory::index_wallet(&env, &user_id_hash, &wallet)
+        });
+        wallets.push_back(wallet);
+    }
+
+    assert_eq!(client.get_total_wallets(), 3);
+    assert_eq!(client.list_wallets(&0, &10), wallets);
+    assert_eq!(
+        client.list_wallets(&1, &1),
+        vec![&env, wallets.get(1).unwrap()]
+    );
+    assert_eq!(client.list_wallets(&3, &10), vec![&env]);
+    assert_eq!(client.list_wallets(&u32::MAX, &u32::MAX), vec![&env]);
+
+    let wallet = wallets.get(2).unwrap();
+    assert!(client.is_factory_wallet(&wallet));
+    assert_eq!(
+        client.get_user_hash(&wallet),
+        BytesN::from_array(&env, &[2; 32])
+    );
+    assert!(!client.is_factory_wallet(&Address::generate(&env)));
+}
+
+#[test]
+#[should_panic(expected = "Not a factory wallet")]
+fn test_get_user_hash_unknown_wallet() {
+    let env = Env::default();
+
+    let admin = Address::generate(&env);
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let usdc = Address::generate(&env);
+    let wasm_hash = BytesN::from_array(&env, &[0u8; 32]);
+
+    let contract_id = env.register(WalletFactory, (&admin, &backend, &vault, &usdc, &wasm_hash));
+    let client = WalletFactoryClient::new(&env, &contract_id);
+
+    client.get_user_hash(&Address::generate(&env));
+}
+
+#[test]
+fn test_predict_wallet_address() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let usdc = env
+        .register_stellar_asset_contract_v2(Address::generate(&env))
+        .address();
+    let wasm_hash = BytesN::from_array(&env, &[0u8; 32]);
+
+    let contract_id = env.register(WalletFactory, (&admin, &backend, &vault, &usdc, &wasm_hash));
+    let client = WalletFactoryClient::new(&env, &contract_id);
+
+    let alice = BytesN::from_array(&env, &[1u8; 32]);
+    let bob = BytesN::from_array(&env, &[2u8; 32]);
+    let predicted = client.predict_wallet_address(&alice);
+    assert_eq!(client.predict_wallet_address(&alice), predicted);
+    assert_ne!(client.predict_wallet_address(&bob), predicted);
+
+    // Not affected by later wallet code changes
+    client.set_wallet_wasm(&admin, &BytesN::from_array(&env, &[1u8; 32]));
+    assert_eq!(client.predict_wallet_address(&alice), predicted);
+
+    // Deposits can land before the wallet is deployed
+    token::StellarAssetClient::new(&env, &usdc).mint(&predicted, &1000);
+    assert_eq!(token::Client::new(&env, &usdc).balance(&predicted), 1000);
+    assert!(!client.has_wallet(&alice));
+}
+
+#[test]
+fn test_create_wallets_reports_existing_users() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let usdc = Address::generate(&env);
+    let wasm_hash = BytesN::from_array(&env, &[0u8; 32]);
+
+    let contract_id = env.register(WalletFactory, (&admin, &backend, &vault, &usdc, &wasm_hash));
+    let client = WalletFactoryClient::new(&env, &contract_id);
+
+    let user_id_hash = BytesN::from_array(&env, &[1u8; 32]);
+    let wallet = Address::generate(&env);
+    env.as_contract(&contract_id, || {
+        WalletFactory::index_wallet(&env, &user_id_hash, &wallet)
+    });
+
+    let results = client.create_wallets(&backend, &vec![&env, user_id_hash.clone(), user_id_hash]);
+    assert_eq!(
+        results,
+        vec![
+            &env,
+            WalletCreation::Existing(wallet.clone()),
+            WalletCreation::Existing(wallet)
+        ]
+    );
+    assert_eq!(client.get_total_wallets(), 1);
+}
+
+#[test]
+#[should_panic(expected = "Batch too large")]
+fn test_create_wallets_batch_too_large() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let usdc = Address::generate(&env);
+    let wasm_hash = BytesN::from_array(&env, &[0u8; 32]);
+
+    let contract_id = env.register(WalletFactory, (&admin, &backend, &vault, &usdc, &wasm_hash));
+    let client = WalletFactoryClient::new(&env, &contract_id);
+
+    let mut user_id_hashes = Vec::new(&env);
+    for _ in 0..21 {
+        user_id_hashes.push_back(BytesN::from_array(&env, &[1u8; 32]));
+    }
+    client.create_wallets(&backend, &user_id_hashes);
+}
+
+#[test]
+#[should_panic(expected = "Only backend")]
+fn test_create_wallets_not_backend() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let usdc = Address::generate(&env);
+    let wasm_hash = BytesN::from_array(&env, &[0u8; 32]);
+
+    let contract_id = env.register(WalletFactory, (&admin, &backend, &vault, &usdc, &wasm_hash));
+    let client = WalletFactoryClient::new(&env, &contract_id);
+
+    client.create_wallets(&admin, &vec![&env, BytesN::from_array(&env, &[1u8; 32])]);
+}
+
+#[test]
+fn test_backend_operators() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let usdc = Address::generate(&env);
+    let wasm_hash = BytesN::from_array(&env, &[0u8; 32]);
+
+    let contract_id = env.register(WalletFactory, (&admin, &backend, &vault, &usdc, &wasm_hash));
+    let client = WalletFactoryClient::new(&env, &contract_id);
+    assert!(client.has_role(&admin, &ADMIN_ROLE));
+    assert!(client.is_backend(&backend));
+
+    let second = Address::generate(&env);
+    client.grant_role(&admin, &second, &BACKEND_ROLE);
+    assert!(client.is_backend(&second));
+
+    // Replacing the primary backend only moves its own role
+    let new_backend = Address::generate(&env);
+    client.update_backend(&admin, &new_backend);
+    assert!(!client.is_backend(&backend));
+    assert!(client.is_backend(&new_backend));
+    assert!(client.is_backend(&second));
+
+    client.revoke_role(&admin, &second, &BACKEND_ROLE);
+    assert!(!client.is_backend(&second));
+}
+
+#[test]
+#[should_panic(expected = "Cannot revoke last admin")]
+fn test_revoke_last_admin() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let usdc = Address::generate(&env);
+    let wasm_hash = BytesN::from_array(&env, &[0u8; 32]);
+
+    let contract_id = env.register(WalletFactory, (&admin, &backend, &vault, &usdc, &wasm_hash));
+    let client = WalletFactoryClient::new(&env, &contract_id);
+
+    // Another admin can be removed while one remains
+    let second = Address::generate(&env);
+    client.grant_role(&admin, &second, &ADMIN_ROLE);
+    client.revoke_role(&admin, &second, &ADMIN_ROLE);
+    assert!(!client.has_role(&second, &ADMIN_ROLE));
+
+    client.revoke_role(&admin, &admin, &ADMIN_ROLE);
+}
+
+#[test]
+#[should_panic(expected = "Only admin")]
+fn test_grant_role_not_admin() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let usdc = Address::generate(&env);
+    let wasm_hash = BytesN::from_array(&env, &[0u8; 32]);
+
+    let contract_id = env.register(WalletFactory, (&admin, &backend, &vault, &usdc, &wasm_hash));
+    let client = WalletFactoryClient::new(&env, &contract_id);
+
+    client.grant_role(&backend, &backend, &COMPLIANCE_ROLE);
+}
+
+#[test]
+#[should_panic(expected = "Only admin or compliance")]
+fn test_freeze_wallet_not_compliance() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let usdc = Address::generate(&env);
+    let wasm_hash = BytesN::from_array(&env, &[0u8; 32]);
+
+    let contract_id = env.register(WalletFactory, (&admin, &backend, &vault, &usdc, &wasm_hash));
+    let client = WalletFactoryClient::new(&env, &contract_id);
+
+    client.freeze_wallet(&backend, &Address::generate(&env));
+}
+
+#[test]
+fn test_wallet_closed_frees_user_id_hash() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let usdc = Address::generate(&env);
+    let wasm_hash = BytesN::from_array(&env, &[0u8; 32]);
+
+    let contract_id = env.register(WalletFactory, (&admin, &backend, &vault, &usdc, &wasm_hash));
+    let client = WalletFactoryClient::new(&env, &contract_id);
+
+    let user_id_hash = BytesN::from_array(&env, &[1u8; 32]);
+    let wallet = Address::generate(&env);
+    env.as_contract(&contract_id, || {
+        WalletFactory::index_wallet(&env, &user_id_hash, &wallet)
+    });
+    let first_address = client.predict_wallet_address(&user_id_hash);
+
+    client.wallet_closed(&wallet);
+
+    assert!(!client.has_wallet(&user_id_hash));
+    assert!(client.is_user_closed(&user_id_hash));
+    assert_eq!(client.get_user_hash(&wallet), user_id_hash);
+    assert_ne!(client.predict_wallet_address(&user_id_hash), first_address);
+
+    // Closing again is rejected
+    let result = client.try_wallet_closed(&wallet);
+    assert!(result.is_err());
+}
+
+#[test]
+#[should_panic(expected = "Not a factory wallet")]
+fn test_wallet_closed_unknown_wallet() {
+    let env = Env::default();
+    env.mock_all_auths();
+
+    let admin = Address::generate(&env);
+    let backend = Address::generate(&env);
+    let vault = Address::generate(&env);
+    let usdc = Address::generate(&env);
+    let wasm_hash = BytesN::from_array(&env, &[0u8; 32]);
+
+    let contract_id = env.register(WalletFactory, (&admin, &backend, &vault, &usdc, &wasm_hash));
+    let client = WalletFactoryClient::new(&env, &contract_id);
+
+    client.wallet_closed(&Address::generate(&env));
+}
+
+#[test]
+fn test_create_wallet_deploys_to_predicted_address() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (client, _, backend, _) = setup_live_factory(&env);
+
+    let user_id_hash = BytesN::from_array(&env, &[1u8; 32]);
+    let predicted = client.predict_wallet_address(&user_id_hash);
+    let wallet = client.create_wallet(&backend, &user_id_hash);
+    assert_eq!(wallet, predicted);
+    assert_eq!(client.get_wallet(&user_id_hash), Some(wallet.clone()));
+    assert_eq!(client.list_wallets(&0, &10), vec![&env, wallet.clone()]);
+
+    let wallet_client = user_wallet::Client::new(&env, &wallet);
+    assert_eq!(wallet_client.get_factory(), Some(client.address.clone()));
+    assert_eq!(wallet_client.get_backend(), backend);
+    assert_eq!(wallet_client.get_version(), 1);
+}
+
+#[test]
+fn test_create_wallets_deploys_new_users() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (client, _, backend, _) = setup_live_factory(&env);
+
+    let alice = BytesN::from_array(&env, &[1u8; 32]);
+    let bob = BytesN::from_array(&env, &[2u8; 32]);
+    let alice_wallet = client.create_wallet(&backend, &alice);
+    let bob_predicted = client.predict_wallet_address(&bob);
+
+    let results = client.create_wallets(&backend, &vec![&env, alice, bob.clone()]);
+    assert_eq!(
+        results,
+        vec![
+            &env,
+            WalletCreation::Existing(alice_wallet),
+            WalletCreation::Created(bob_predicted.clone())
+        ]
+    );
+    assert_eq!(client.get_wallet(&bob), Some(bob_predicted.clone()));
+    assert_eq!(client.get_total_wallets(), 2);
+    assert_eq!(
+        user_wallet::Client::new(&env, &bob_predicted).get_factory(),
+        Some(client.address.clone())
+    );
+}
+
+#[test]
+fn test_upgrade_wallets_upgrades_live_wallets() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (client, admin, backend, _) = setup_live_factory(&env);
+
+    let wallet = client.create_wallet(&backend, &BytesN::from_array(&env, &[1u8; 32]));
+    let opted_out = client.create_wallet(&backend, &BytesN::from_array(&env, &[2u8; 32]));
+    let owner = Address::generate(&env);
+    let opted_out_client = user_wallet::Client::new(&env, &opted_out);
+    opted_out_client.set_owner(&backend, &owner, &BytesN::from_array(&env, &[1u8; 32]));
+    opted_out_client.set_upgrade_opt_out(&owner, &true);
+
+    let wasm_hash = client.get_wallet_wasm();
+    assert_eq!(client.set_wallet_wasm(&admin, &wasm_hash), 2);
+
+    let wallets = vec![
+        &env,
+        wallet.clone(),
+        opted_out.clone(),
+        Address::generate(&env),
+    ];
+    assert_eq!(
+        client.upgrade_wallets(&admin, &wallets),
+        vec![&env, true, false, false]
+    );
+    assert_eq!(user_wallet::Client::new(&env, &wallet).get_version(), 2);
+    assert_eq!(opted_out_client.get_version(), 1);
+
+    // Already current
+    assert_eq!(
+        client.upgrade_wallets(&admin, &vec![&env, wallet]),
+        vec![&env, false]
+    );
+}
+
+#[test]
+fn test_freeze_and_approve_exit_live_wallet() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (client, admin, backend, usdc) = setup_live_factory(&env);
+
+    let compliance = Address::generate(&env);
+    client.grant_role(&admin, &compliance, &COMPLIANCE_ROLE);
+    let wallet = client.create_wallet(&backend, &BytesN::from_array(&env, &[1u8; 32]));
+    let wallet_client = user_wallet::Client::new(&env, &wallet);
+    token::StellarAssetClient::new(&env, &usdc).mint(&wallet, &1000);
+
+    // Exit needs the wallet frozen first
+    let to = Address::generate(&env);
+    assert!(client.try_approve_exit(&compliance, &wallet, &to).is_err());
+
+    client.freeze_wallet(&compliance, &wallet);
+    assert_eq!(
+        wallet_client.get_status(),
+        user_wallet::WalletStatus::Frozen
+    );
+
+    client.approve_exit(&compliance, &wallet, &to);
+    let usdc_client = token::Client::new(&env, &usdc);
+    assert_eq!(usdc_client.balance(&to), 1000);
+    assert_eq!(usdc_client.balance(&wallet), 0);
+    assert_eq!(
+        wallet_client.get_status(),
+        user_wallet::WalletStatus::Frozen
+    );
+
+    client.unfreeze_wallet(&compliance, &wallet);
+    assert_eq!(
+        wallet_client.get_status(),
+        user_wallet::WalletStatus::Active
+    );
+}
+
+#[test]
+fn test_closed_live_wallet_frees_user_id_hash() {
+    let env = Env::default();
+    env.mock_all_auths();
+    let (client, _, backend, usdc) = setup_live_factory(&env);
+
+    let user_id_hash = BytesN::from_array(&env, &[1u8; 32]);
+    let wallet = client.create_wallet(&backend, &user_id_hash);
+    let wallet_client = user_wallet::Client::new(&env, &wallet);
+    let owner = Address::generate(&env);
+    wallet_client.set_owner(&backend, &owner, &BytesN::from_array(&env, &[1u8; 32]));
+    token::StellarAssetClient::new(&env, &usdc).mint(&wallet, &1000);
+
+    // Closing the wallet reports back through `wallet_closed`
+    wallet_client.close(&owner);
+    assert_eq!(token::Client::new(&env, &usdc).balance(&owner), 1000);
+    assert!(!client.has_wallet(&user_id_hash));
+    assert!(client.is_user_closed(&user_id_hash));
+
+    let predicted = client.predict_wallet_address(&user_id_hash);
+    assert_ne!(predicted, wallet);
+    assert_eq!(client.create_wallet(&backend, &user_id_hash), predicted);
+    assert!(!client.is_user_closed(&user_id_hash));
+    assert_eq!(client.get_total_wallets(), 2);
+}